@@ -1,12 +1,25 @@
 use crate::{
-    commands::{info, Poll, Push},
+    cassette::{Player, Recorder},
+    commands::{
+        dlq_drop, dlq_list, dlq_retry, export, import, import_archive, info, InfoOptions, Poll,
+        PreviewFormat, Push,
+    },
     config::{PollConfig, PushConfig},
-    database::Database,
-    Config,
+    database::{Access, Database, SCHEMA_VERSION},
+    queue,
+    retry::Retry,
+    twitter::TwitterArchive,
+    Cassette, Config,
 };
 use anyhow::{anyhow, Context, Result};
-use reqwest::Client;
-use tracing::{info, instrument};
+use chrono::Utc;
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware as Client};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::Path,
+};
+use tracing::{info, instrument, warn};
 
 /// Application entry.
 pub struct App {
@@ -16,47 +29,207 @@ pub struct App {
 }
 
 impl App {
-    pub fn new(config: Config) -> Self {
-        let database = Database::open(config.rocksdb_path.as_path());
-        let client = Client::new();
-        Self {
+    /// Opens database of config, and upgrades it if it's written by an older version of Pigeon.
+    pub fn new(config: Config) -> Result<Self> {
+        let app = Self::open(config)?;
+        app.database
+            .migrate()
+            .with_context(|| "Failed to migrate database")?;
+        Ok(app)
+    }
+
+    /// Opens database of config without upgrading it.
+    pub fn open(config: Config) -> Result<Self> {
+        let access = Access::ReadWrite(config.lock_timeout()?);
+        Self::with_access(config, access)
+    }
+
+    /// Opens database of config for reading only, while another Pigeon process may be using it.
+    /// Database is neither locked nor upgraded, so only `info` command works.
+    pub fn open_read_only(config: Config) -> Result<Self> {
+        Self::with_access(config, Access::ReadOnly)
+    }
+
+    fn with_access(config: Config, access: Access) -> Result<Self> {
+        let database = Database::from_config(&config, access)?;
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(Retry(config.retry_policy()?))
+            .build();
+        Ok(Self {
             database,
             client,
             config,
+        })
+    }
+
+    /// Records HTTP requests and responses of commands to a cassette, or replays them from it.
+    /// Secrets in config are redacted from recorded cassettes.
+    pub fn cassette(mut self, cassette: Cassette) -> Result<Self> {
+        let secrets = self.config.secrets();
+        // Retries are outside of cassette, so that each attempt is recorded and replayed.
+        let builder =
+            ClientBuilder::new(reqwest::Client::new()).with(Retry(self.config.retry_policy()?));
+        self.client = match cassette {
+            Cassette::Record(dir) => builder.with(Recorder::new(dir, secrets)?),
+            Cassette::Replay(dir) => builder.with(Player::load(dir, secrets)?),
         }
+        .build();
+        Ok(self)
     }
 
     #[instrument(skip_all)]
     pub async fn poll(&mut self) -> Result<()> {
         info!("Starting to poll Twitter timeline from config.");
+        let limits = self.config.limits()?;
         Poll::new(
             self.config.twitter_token.take(),
+            self.config.twitter_api()?,
             self.poll_config()?,
             &self.client,
             &self.database,
         )?
         .run()
         .await
-        .with_context(|| "Failed to execute poll command")
+        .with_context(|| "Failed to execute poll command")?;
+        queue::enforce(&self.database, &limits, Utc::now())
+            .with_context(|| "Failed to apply queue limits")?;
+        Ok(())
     }
 
     #[instrument(skip_all)]
     pub async fn push(&mut self) -> Result<()> {
         info!("Starting to push timeline to Telegram channel(s) from config.");
-        Push::new(
+        self.push_command()?
+            .run()
+            .await
+            .with_context(|| "Failed to execute push command")
+    }
+
+    /// Prints messages that `push` would send, without sending them or changing database.
+    #[instrument(skip_all)]
+    pub async fn push_dry_run(&mut self, format: PreviewFormat) -> Result<()> {
+        info!("Starting a dry run of pushing timeline from config.");
+        self.push_command()?
+            .dry_run(format)
+            .run()
+            .await
+            .with_context(|| "Failed to execute push command in dry run")
+    }
+
+    pub fn info(&self, options: &InfoOptions) -> Result<()> {
+        info!("Overview info of database.");
+        let limits = self.config.limits()?;
+        info(&self.database, &limits, options).with_context(|| "Failed to execute info command")
+    }
+
+    fn push_command(&mut self) -> Result<Push<'_>> {
+        let retention = self.config.retention()?;
+        let thread_max_age = self.config.thread_max_age()?;
+        let push = Push::new(
             self.config.telegram_token.take(),
+            self.config.telegram_api()?,
+            self.config.slack_api()?,
             self.push_config()?,
             &self.client,
             &mut self.database,
-        )?
-        .run()
-        .await
-        .with_context(|| "Failed to execute push command")
+        )
+        .thread_max_age(thread_max_age);
+        Ok(match retention {
+            Some(retention) => push.archive(retention),
+            None => push,
+        })
     }
 
-    pub fn info(&self) -> Result<()> {
-        info!("Overview info of database.");
-        info(&self.database).with_context(|| "Failed to execute info command")
+    /// Exports database to a JSON Lines file, or to stdout if no path is given.
+    pub fn export(&self, path: Option<&Path>) -> Result<()> {
+        info!("Exporting database.");
+        match path {
+            Some(path) => {
+                let file = File::create(path)
+                    .with_context(|| format!("Could not create export file: {}", path.display()))?;
+                export(&self.database, BufWriter::new(file))
+            }
+            None => export(&self.database, io::stdout().lock()),
+        }
+        .map(|_| ())
+        .with_context(|| "Failed to execute export command")
+    }
+
+    /// Imports database from a JSON Lines file written by `export`.
+    pub fn import(&self, path: &Path) -> Result<()> {
+        info!("Importing database from {}.", path.display());
+        let file = File::open(path)
+            .with_context(|| format!("Could not open import file: {}", path.display()))?;
+        import(&self.database, BufReader::new(file))
+            .map(|_| ())
+            .with_context(|| "Failed to execute import command")
+    }
+
+    /// Queues tweets of an official Twitter archive ZIP, to be pushed to one destination only.
+    /// The account username is read from the archive, unless it's given.
+    pub fn import_archive(
+        &mut self,
+        path: &Path,
+        destination: &str,
+        username: Option<String>,
+    ) -> Result<()> {
+        info!("Importing Twitter archive from {}.", path.display());
+        let file = File::open(path)
+            .with_context(|| format!("Could not open Twitter archive: {}", path.display()))?;
+        let archive = TwitterArchive::read(BufReader::new(file))?;
+        let push_config = self.push_config()?;
+        let count = import_archive(&self.database, archive, username, destination, &push_config)
+            .with_context(|| "Failed to execute import-archive command")?;
+        if let Some(max) = self.config.limits()?.max_queue_per_user {
+            if count > max {
+                warn!("Imported {count} tweets, over max_queue_per_user {max}, so some of them are dropped after the next poll.");
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints tweets that destinations permanently rejected.
+    pub fn dlq_list(&self) -> Result<()> {
+        dlq_list(&self.database)
+            .map(|_| ())
+            .with_context(|| "Failed to list dead letters")
+    }
+
+    /// Queues dead letters of keys, or all of them if `keys` is `None`, to be pushed again.
+    pub fn dlq_retry(&mut self, keys: Option<&[String]>) -> Result<()> {
+        let push_config = self.push_config()?;
+        dlq_retry(&self.database, keys, &push_config)
+            .map(|_| ())
+            .with_context(|| "Failed to retry dead letters")
+    }
+
+    /// Deletes dead letters of keys, or all of them if `keys` is `None`.
+    pub fn dlq_drop(&self, keys: Option<&[String]>) -> Result<()> {
+        dlq_drop(&self.database, keys)
+            .map(|_| ())
+            .with_context(|| "Failed to drop dead letters")
+    }
+
+    /// Upgrades database to the latest schema version.
+    pub fn migrate(&self) -> Result<()> {
+        self.database
+            .migrate()
+            .with_context(|| "Failed to migrate database")
+    }
+
+    /// Prints schema version of database and migrations which are not applied yet,
+    /// returns whether database is up to date.
+    pub fn check_migrations(&self) -> Result<bool> {
+        let version = self.database.schema_version()?;
+        let pending = self.database.pending_migrations()?;
+        println!("Database schema version: {version}, latest: {SCHEMA_VERSION}");
+        for migration in &pending {
+            println!(
+                "Pending migration to version {}: {}",
+                migration.version, migration.description
+            );
+        }
+        Ok(pending.is_empty())
     }
 
     /// Returns poll configs that are included.
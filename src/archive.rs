@@ -0,0 +1,252 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::{database::Database, twitter::Tweet};
+
+/// How long, or how many, archived tweets are kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Retention {
+    pub(crate) max_age: Option<Duration>,
+    pub(crate) max_entries: Option<usize>,
+}
+
+/// A pushed tweet kept in archive column family, keyed by its timeline key.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Record {
+    pub(crate) tweet: Tweet,
+    pub(crate) deliveries: Vec<Delivered>,
+    /// RFC3339 time the tweet is archived at, which retention policy is applied to.
+    pub(crate) archived_at: String,
+}
+
+/// Delivery of a tweet to one destination.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub(crate) struct Delivered {
+    pub(crate) destination: String,
+    #[serde(flatten)]
+    pub(crate) receipt: Receipt,
+}
+
+/// Value of a delivery column family entry.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub(crate) struct Receipt {
+    /// ID of the message created by destination, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) message_id: Option<String>,
+    /// RFC3339 time the tweet is accepted by destination at, or skipped at.
+    pub(crate) sent_at: String,
+    /// Whether the tweet is marked as delivered without being sent, like for routes other
+    /// than the one that a tweet imported from an archive or a retried dead letter is for.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub(crate) skipped: bool,
+}
+
+impl Receipt {
+    pub(crate) fn new(message_id: Option<String>, sent_at: &str) -> Self {
+        Self {
+            message_id,
+            sent_at: sent_at.into(),
+            skipped: false,
+        }
+    }
+
+    /// Creates a receipt of a tweet which is not sent to the destination, so that it's never
+    /// sent there, nor archived as a delivery.
+    pub(crate) fn skipped(skipped_at: &str) -> Self {
+        Self {
+            message_id: None,
+            sent_at: skipped_at.into(),
+            skipped: true,
+        }
+    }
+
+    pub(crate) fn to_vec(&self) -> Result<Vec<u8>> {
+        serde_json::to_vec(self).with_context(|| "could not serialize delivery receipt to json")
+    }
+
+    pub(crate) fn from_slice(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes).with_context(|| "Invalid delivery receipt")
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// Moves a pushed tweet along with its deliveries from timeline to archive column family.
+/// Skipped receipts are not deliveries, and tweets that are not delivered anywhere are not archived.
+///
+/// Note: it doesn't delete the tweet from timeline, which is left to push command.
+pub(crate) fn archive(database: &Database, key: &[u8], now: DateTime<Utc>) -> Result<()> {
+    let Some(value) = database.get_cf("timeline", key)? else {
+        return Ok(());
+    };
+    let tweet: Tweet = serde_json::from_slice(&value)?;
+
+    // Delivery keys of a tweet are prefixed by its timeline key and a slash.
+    let prefix = [key, b"/"].concat();
+    let mut deliveries = vec![];
+    for entry in database.iterator_cf_from("delivery", &prefix)? {
+        let (delivery_key, value) = entry?;
+        let Some(destination) = delivery_key.strip_prefix(prefix.as_slice()) else {
+            break;
+        };
+        let receipt = Receipt::from_slice(&value)?;
+        if receipt.skipped {
+            continue;
+        }
+        deliveries.push(Delivered {
+            destination: String::from_utf8(destination.to_vec())?,
+            receipt,
+        });
+    }
+    if deliveries.is_empty() {
+        return Ok(());
+    }
+
+    let record = Record {
+        tweet,
+        deliveries,
+        archived_at: now.to_rfc3339(),
+    };
+    debug!("Archive tweet: {}", String::from_utf8_lossy(key));
+    database.put_cf("archive", key, serde_json::to_vec(&record)?)
+}
+
+/// Deletes archived tweets beyond retention, returns how many are deleted.
+pub(crate) fn prune(
+    database: &Database,
+    retention: &Retention,
+    now: DateTime<Utc>,
+) -> Result<usize> {
+    if retention == &Retention::default() {
+        return Ok(0);
+    }
+
+    let mut entries = vec![];
+    for entry in database.iterator_cf("archive")? {
+        let (key, value) = entry?;
+        let record: Record = serde_json::from_slice(&value)?;
+        let archived_at = DateTime::parse_from_rfc3339(&record.archived_at)?.to_utc();
+        entries.push((archived_at, key));
+    }
+    // Oldest first.
+    entries.sort();
+
+    let expired = retention.max_age.map_or(0, |max_age| {
+        entries.partition_point(|(archived_at, _)| *archived_at + max_age < now)
+    });
+    let excess = retention
+        .max_entries
+        .map_or(0, |max_entries| entries.len().saturating_sub(max_entries));
+    let pruned = expired.max(excess);
+    for (_, key) in &entries[..pruned] {
+        database.delete_cf("archive", key)?;
+    }
+    if pruned > 0 {
+        info!("Pruned {pruned} tweets from archive.");
+    }
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration};
+
+    use super::{archive, prune, Delivered, Receipt, Record, Retention};
+    use crate::database::Database;
+
+    #[test]
+    fn archive_and_prune() {
+        let database = Database::memory();
+        let now = DateTime::parse_from_rfc3339("2024-01-10T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        for id in ["1001", "1002", "1003"] {
+            let key = format!("TwitterDev:{id}");
+            let tweet =
+                serde_json::json!({"id": id, "created_at": "2024-01-01T00:00:00Z", "text": "Hi"});
+            database
+                .put_cf("timeline", &key, tweet.to_string())
+                .unwrap();
+            if id != "1003" {
+                let receipt = Receipt::new(Some("42".into()), "2024-01-01T00:00:00Z");
+                database
+                    .put_cf(
+                        "delivery",
+                        format!("{key}/telegram:@channel"),
+                        receipt.to_vec().unwrap(),
+                    )
+                    .unwrap();
+            }
+        }
+        database
+            .put_cf(
+                "delivery",
+                "TwitterDev:1001/discord:xyz",
+                Receipt::new(None, "2024-01-02T00:00:00Z").to_vec().unwrap(),
+            )
+            .unwrap();
+        // Tweets only marked as delivered are not delivered anywhere.
+        database
+            .put_cf(
+                "delivery",
+                "TwitterDev:1003/discord:xyz",
+                Receipt::skipped("2024-01-02T00:00:00Z").to_vec().unwrap(),
+            )
+            .unwrap();
+        assert!(Receipt::from_slice(b"2024-01-02T00:00:00Z").is_err());
+
+        archive(&database, b"TwitterDev:1001", now - Duration::days(5)).unwrap();
+        archive(&database, b"TwitterDev:1002", now).unwrap();
+        // Tweets delivered nowhere are not archived.
+        archive(&database, b"TwitterDev:1003", now).unwrap();
+
+        let record: Record = serde_json::from_slice(
+            &database
+                .get_cf("archive", "TwitterDev:1001")
+                .unwrap()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                Delivered {
+                    destination: "discord:xyz".into(),
+                    receipt: Receipt::new(None, "2024-01-02T00:00:00Z"),
+                },
+                Delivered {
+                    destination: "telegram:@channel".into(),
+                    receipt: Receipt::new(Some("42".into()), "2024-01-01T00:00:00Z"),
+                },
+            ],
+            record.deliveries
+        );
+        assert!(database
+            .get_cf("archive", "TwitterDev:1003")
+            .unwrap()
+            .is_none());
+
+        let retention = Retention {
+            max_age: Some(Duration::days(7)),
+            max_entries: Some(2),
+        };
+        assert_eq!(0, prune(&database, &retention, now).unwrap());
+        let retention = Retention {
+            max_age: Some(Duration::days(3)),
+            ..Default::default()
+        };
+        assert_eq!(1, prune(&database, &retention, now).unwrap());
+        assert!(database
+            .get_cf("archive", "TwitterDev:1001")
+            .unwrap()
+            .is_none());
+        let retention = Retention {
+            max_entries: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(1, prune(&database, &retention, now).unwrap());
+    }
+}
@@ -0,0 +1,338 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use http::Extensions;
+use reqwest::{
+    header::{HeaderMap, AUTHORIZATION},
+    Request, Response, ResponseBuilderExt,
+};
+use reqwest_middleware::{Middleware, Next};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+use tracing::debug;
+
+/// Replaces secrets in recorded requests and responses.
+const REDACTED: &str = "<redacted>";
+
+/// A directory that HTTP requests and responses are recorded to, or replayed from.
+#[derive(Debug, Clone)]
+pub enum Cassette {
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+/// A request and its response, saved as one JSON file in a cassette.
+#[derive(Debug, Serialize, Deserialize)]
+struct Interaction {
+    request: SavedRequest,
+    response: SavedResponse,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedRequest {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    /// Streamed bodies, like multipart forms, are not saved.
+    body: Option<Body>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Body,
+}
+
+/// A body saved as text if it's UTF-8, otherwise as base64.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Body {
+    Text(String),
+    Base64(String),
+}
+
+/// Middleware recording every request and response to a cassette, with secrets redacted.
+pub(crate) struct Recorder {
+    dir: PathBuf,
+    secrets: Vec<String>,
+    count: AtomicUsize,
+}
+
+/// Middleware answering requests with responses from a cassette, without network.
+pub(crate) struct Player {
+    secrets: Vec<String>,
+    /// Interactions which are not replayed yet, in recorded order.
+    interactions: Mutex<Vec<Interaction>>,
+}
+
+impl Recorder {
+    pub(crate) fn new(dir: PathBuf, secrets: Vec<String>) -> Result<Self> {
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Could not create cassette directory: {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            secrets,
+            count: AtomicUsize::new(0),
+        })
+    }
+
+    fn save(&self, interaction: &Interaction) -> Result<()> {
+        let n = self.count.fetch_add(1, Ordering::SeqCst);
+        let path = self.dir.join(format!("{n:04}.json"));
+        debug!("Record HTTP interaction to {}", path.display());
+        fs::write(&path, serde_json::to_vec_pretty(interaction)?)
+            .with_context(|| format!("Could not write cassette file: {}", path.display()))
+    }
+}
+
+impl Player {
+    pub(crate) fn load(dir: PathBuf, secrets: Vec<String>) -> Result<Self> {
+        let mut paths = fs::read_dir(&dir)
+            .with_context(|| format!("Could not read cassette directory: {}", dir.display()))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.sort();
+
+        let mut interactions = vec![];
+        for path in paths
+            .iter()
+            .filter(|path| path.extension() == Some("json".as_ref()))
+        {
+            let bytes = fs::read(path)?;
+            let interaction = serde_json::from_slice(&bytes)
+                .with_context(|| format!("Invalid cassette file: {}", path.display()))?;
+            interactions.push(interaction);
+        }
+        Ok(Self {
+            secrets,
+            interactions: Mutex::new(interactions),
+        })
+    }
+}
+
+#[async_trait]
+impl Middleware for Recorder {
+    async fn handle(
+        &self,
+        request: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let saved_request = SavedRequest::new(&request, &self.secrets);
+        let url = request.url().clone();
+        let response = next.run(request, extensions).await?;
+
+        // Keep response parts to rebuild the response for caller.
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = response.bytes().await?;
+        let interaction = Interaction {
+            request: saved_request,
+            response: SavedResponse {
+                status: status.as_u16(),
+                headers: redact_headers(&headers, &self.secrets),
+                body: Body::new(&bytes, &self.secrets),
+            },
+        };
+        self.save(&interaction)
+            .map_err(reqwest_middleware::Error::Middleware)?;
+
+        let mut builder = http::Response::builder().status(status).url(url);
+        if let Some(builder_headers) = builder.headers_mut() {
+            *builder_headers = headers;
+        }
+        Ok(builder
+            .body(bytes)
+            .map_err(|e| reqwest_middleware::Error::Middleware(e.into()))?
+            .into())
+    }
+}
+
+#[async_trait]
+impl Middleware for Player {
+    async fn handle(
+        &self,
+        request: Request,
+        _extensions: &mut Extensions,
+        _next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let saved_request = SavedRequest::new(&request, &self.secrets);
+        let interaction = {
+            // Unwrap it directly since the lock is never held across a panic.
+            let mut interactions = self.interactions.lock().unwrap();
+            let position = interactions.iter().position(|interaction| {
+                interaction.request.method == saved_request.method
+                    && interaction.request.url == saved_request.url
+            });
+            position.map(|position| interactions.remove(position))
+        }
+        .ok_or_else(|| {
+            reqwest_middleware::Error::Middleware(anyhow!(
+                "No recorded response in cassette for {} {}",
+                saved_request.method,
+                saved_request.url
+            ))
+        })?;
+        debug!(
+            "Replay HTTP interaction: {} {}",
+            saved_request.method, saved_request.url
+        );
+
+        let mut builder = http::Response::builder()
+            .status(interaction.response.status)
+            .url(request.url().clone());
+        for (name, value) in &interaction.response.headers {
+            builder = builder.header(name, value);
+        }
+        let body = interaction
+            .response
+            .body
+            .into_bytes()
+            .map_err(reqwest_middleware::Error::Middleware)?;
+        Ok(builder
+            .body(body)
+            .map_err(|e| reqwest_middleware::Error::Middleware(e.into()))?
+            .into())
+    }
+}
+
+impl SavedRequest {
+    fn new(request: &Request, secrets: &[String]) -> Self {
+        Self {
+            method: request.method().to_string(),
+            url: redact(request.url().as_str(), secrets),
+            headers: redact_headers(request.headers(), secrets),
+            body: request
+                .body()
+                .and_then(|body| body.as_bytes())
+                .map(|bytes| Body::new(bytes, secrets)),
+        }
+    }
+}
+
+impl Body {
+    fn new(bytes: &[u8], secrets: &[String]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => Self::Text(redact(text, secrets)),
+            Err(_) => Self::Base64(STANDARD.encode(bytes)),
+        }
+    }
+
+    fn into_bytes(self) -> Result<Vec<u8>> {
+        match self {
+            Self::Text(text) => Ok(text.into_bytes()),
+            Self::Base64(base64) => Ok(STANDARD.decode(base64)?),
+        }
+    }
+}
+
+fn redact(text: &str, secrets: &[String]) -> String {
+    secrets
+        .iter()
+        .filter(|secret| !secret.is_empty())
+        .fold(text.to_string(), |text, secret| {
+            // Secrets in URLs may be percent encoded.
+            let encoded =
+                url::form_urlencoded::byte_serialize(secret.as_bytes()).collect::<String>();
+            text.replace(secret.as_str(), REDACTED)
+                .replace(&encoded, REDACTED)
+        })
+}
+
+fn redact_headers(headers: &HeaderMap, secrets: &[String]) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = match name {
+                &AUTHORIZATION => REDACTED.to_string(),
+                _ => redact(&String::from_utf8_lossy(value.as_bytes()), secrets),
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest_middleware::ClientBuilder;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::{Player, Recorder};
+    use crate::Config;
+
+    #[tokio::test]
+    async fn record_and_replay() {
+        let dir = std::env::temp_dir().join(format!("pigeon-cassette-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let server = MockServer::start().await;
+        let webhook_url = format!("{}/hook?token=s3cret", server.uri());
+        let toml_str = format!(
+            r#"
+            telegram_token = "123:abc"
+
+            [[push]]
+            included = true
+            from = "TwitterDev"
+            kind = "webhook"
+            url = "{webhook_url}"
+            "#
+        );
+        let secrets = toml::from_str::<Config>(&toml_str).unwrap().secrets();
+        Mock::given(method("POST"))
+            .and(path("/bot123:abc/sendMessage"))
+            .respond_with(
+                ResponseTemplate::new(400)
+                    .set_body_string(r#"{"ok":false,"description":"Bad Request: chat not found"}"#),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let url = format!("{}/bot123:abc/sendMessage", server.uri());
+
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(Recorder::new(dir.clone(), secrets.clone()).unwrap())
+            .build();
+        let response = client.post(&url).bearer_auth("xxx").send().await.unwrap();
+        assert_eq!(400, response.status().as_u16());
+        let response = client.post(&webhook_url).send().await.unwrap();
+        assert_eq!(204, response.status().as_u16());
+        drop(server);
+
+        let cassette = std::fs::read_to_string(dir.join("0000.json")).unwrap();
+        assert!(!cassette.contains("123:abc"));
+        assert!(!cassette.contains("Bearer xxx"));
+        assert!(cassette.contains("/bot<redacted>/sendMessage"));
+        let cassette = std::fs::read_to_string(dir.join("0001.json")).unwrap();
+        assert!(!cassette.contains("s3cret"));
+        assert!(cassette.contains("/hook?<redacted>"));
+
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(Player::load(dir.clone(), secrets).unwrap())
+            .build();
+        let response = client.post(&url).send().await.unwrap();
+        assert_eq!(400, response.status().as_u16());
+        assert!(response.text().await.unwrap().contains("chat not found"));
+        // Every interaction is replayed once.
+        assert!(client.post(&url).send().await.is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
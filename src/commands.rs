@@ -1,7 +1,15 @@
+mod dlq;
+mod export;
+mod import_archive;
 mod info;
 mod poll;
 mod push;
 
+pub(crate) use dlq::{drop as dlq_drop, list as dlq_list, retry as dlq_retry};
+pub(crate) use export::{export, import};
+pub(crate) use import_archive::import_archive;
 pub(crate) use info::info;
+pub use info::{InfoFormat, InfoOptions};
 pub(crate) use poll::Poll;
+pub use push::PreviewFormat;
 pub(crate) use push::Push;
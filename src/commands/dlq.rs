@@ -0,0 +1,226 @@
+use anyhow::{bail, Result};
+use chrono::Utc;
+use std::{collections::HashSet, str};
+use tabled::{Table, Tabled};
+use tracing::info;
+
+use crate::{
+    archive::Receipt,
+    config::PushConfig,
+    database::{Database, WriteBatch},
+    dead_letter::DeadLetter,
+};
+
+/// A tweet in dead letter queue.
+#[derive(Debug, Tabled)]
+struct DeadLetterInfo {
+    key: String,
+    status: u16,
+    error: String,
+    failed_at: String,
+}
+
+/// Prints tweets in dead letter queue, returns how many there are.
+pub(crate) fn list(database: &Database) -> Result<usize> {
+    let mut rows = vec![];
+    for (key, dead_letter) in read(database, None)? {
+        rows.push(DeadLetterInfo {
+            key,
+            status: dead_letter.status,
+            error: dead_letter.error,
+            failed_at: dead_letter.failed_at,
+        });
+    }
+    println!("{}", Table::new(&rows));
+    Ok(rows.len())
+}
+
+/// Queues dead letters of `keys`, or all of them, in timeline again, returns how many are queued.
+///
+/// A tweet which is no longer in timeline is queued only for the destination that rejected it,
+/// other routes from the same account are marked as delivered, like tweets of Twitter archives.
+pub(crate) fn retry(
+    database: &Database,
+    keys: Option<&[String]>,
+    push_config: &[PushConfig],
+) -> Result<usize> {
+    let now = Utc::now().to_rfc3339();
+    let skipped = Receipt::skipped(&now).to_vec()?;
+    let mut batch = WriteBatch::default();
+    // Timeline keys of tweets queued again by this batch.
+    let mut requeued = HashSet::new();
+    let dead_letters = read(database, keys)?;
+    for (key, dead_letter) in &dead_letters {
+        // Unwrap it directly since dead letters are keyed like deliveries.
+        let (timeline_key, destination_id) = key.split_once('/').unwrap();
+        let (username, _) = timeline_key.split_once(':').unwrap_or_default();
+        batch.delete_cf("dead_letter", key);
+        if requeued.contains(timeline_key) {
+            // Marked as delivered by an earlier dead letter of the same tweet.
+            batch.delete_cf("delivery", key);
+        } else if database.get_cf("timeline", timeline_key)?.is_none() {
+            batch.put_cf(
+                "timeline",
+                timeline_key,
+                serde_json::to_vec(&dead_letter.tweet.clone().queued(&now))?,
+            );
+            for cfg in push_config.iter().filter(|cfg| cfg.from == username) {
+                let id = cfg.destination.id();
+                if id != destination_id {
+                    batch.put_cf("delivery", format!("{timeline_key}/{id}"), &skipped);
+                }
+            }
+            requeued.insert(timeline_key);
+        }
+    }
+    database.write(batch)?;
+    info!("Queued {} dead letters again.", dead_letters.len());
+    Ok(dead_letters.len())
+}
+
+/// Deletes dead letters of `keys`, or all of them, returns how many are deleted.
+pub(crate) fn drop(database: &Database, keys: Option<&[String]>) -> Result<usize> {
+    let mut batch = WriteBatch::default();
+    let dead_letters = read(database, keys)?;
+    for (key, _) in &dead_letters {
+        batch.delete_cf("dead_letter", key);
+    }
+    database.write(batch)?;
+    info!("Dropped {} dead letters.", dead_letters.len());
+    Ok(dead_letters.len())
+}
+
+/// Reads dead letters of `keys`, or all of them in key order.
+fn read(database: &Database, keys: Option<&[String]>) -> Result<Vec<(String, DeadLetter)>> {
+    let mut dead_letters = vec![];
+    match keys {
+        Some(keys) => {
+            for key in keys {
+                let Some(value) = database.get_cf("dead_letter", key)? else {
+                    bail!("No dead letter of key: {key}");
+                };
+                dead_letters.push((key.clone(), DeadLetter::from_slice(&value)?));
+            }
+        }
+        None => {
+            for entry in database.iterator_cf("dead_letter")? {
+                let (key, value) = entry?;
+                let key = str::from_utf8(&key)?.to_string();
+                dead_letters.push((key, DeadLetter::from_slice(&value)?));
+            }
+        }
+    }
+    Ok(dead_letters)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use reqwest::StatusCode;
+
+    use super::{drop, list, retry};
+    use crate::{
+        archive::{archive, Delivered, Receipt, Record},
+        config::Config,
+        database::Database,
+        dead_letter::DeadLetter,
+    };
+
+    #[test]
+    fn retry_and_drop() {
+        let toml_str = r#"
+        [[push]]
+        included = true
+        from = "TwitterDev"
+        username = "@channel"
+
+        [[push]]
+        included = true
+        from = "TwitterDev"
+        kind = "discord"
+        webhook_url = "https://discord.com/api/webhooks/xyz/token"
+        "#;
+        let push_config = toml::from_str::<Config>(toml_str).unwrap().push.unwrap();
+        let database = Database::memory();
+        for id in ["1001", "1002", "1003"] {
+            let tweet = serde_json::from_value(
+                serde_json::json!({"id": id, "created_at": "2024-01-01T00:00:00Z", "text": "Hi"}),
+            )
+            .unwrap();
+            let dead_letter = DeadLetter::new(
+                tweet,
+                StatusCode::BAD_REQUEST,
+                "Bad Request: can't parse entities".into(),
+                "2024-01-02T00:00:00Z",
+            );
+            database
+                .put_cf(
+                    "dead_letter",
+                    format!("TwitterDev:{id}/telegram:@channel"),
+                    dead_letter.to_vec().unwrap(),
+                )
+                .unwrap();
+        }
+        // The second tweet is still queued for another destination.
+        database
+            .put_cf("timeline", "TwitterDev:1002", "{}")
+            .unwrap();
+        assert_eq!(3, list(&database).unwrap());
+
+        let keys = ["TwitterDev:1004/telegram:@channel".to_string()];
+        assert!(retry(&database, Some(&keys), &push_config).is_err());
+        let keys = ["TwitterDev:1003/telegram:@channel".to_string()];
+        assert_eq!(1, drop(&database, Some(&keys)).unwrap());
+        assert!(database
+            .get_cf("timeline", "TwitterDev:1003")
+            .unwrap()
+            .is_none());
+        assert_eq!(2, retry(&database, None, &push_config).unwrap());
+        assert!(database
+            .get_cf("timeline", "TwitterDev:1001")
+            .unwrap()
+            .is_some());
+        assert!(database
+            .get_cf("delivery", "TwitterDev:1001/discord:xyz")
+            .unwrap()
+            .is_some());
+        assert!(database
+            .get_cf("delivery", "TwitterDev:1001/telegram:@channel")
+            .unwrap()
+            .is_none());
+        // Once it's pushed, only the real delivery is archived.
+        let receipt = Receipt::new(Some("42".into()), "2024-01-03T00:00:00Z");
+        database
+            .put_cf(
+                "delivery",
+                "TwitterDev:1001/telegram:@channel",
+                receipt.to_vec().unwrap(),
+            )
+            .unwrap();
+        archive(&database, b"TwitterDev:1001", Utc::now()).unwrap();
+        let record: Record = serde_json::from_slice(
+            &database
+                .get_cf("archive", "TwitterDev:1001")
+                .unwrap()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            vec![Delivered {
+                destination: "telegram:@channel".into(),
+                receipt,
+            }],
+            record.deliveries
+        );
+        // Tweets still queued are left as they are.
+        assert_eq!(0, list(&database).unwrap());
+        assert_eq!(
+            Some(b"{}".to_vec()),
+            database.get_cf("timeline", "TwitterDev:1002").unwrap()
+        );
+        assert!(database
+            .get_cf("delivery", "TwitterDev:1002/discord:xyz")
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,190 @@
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+use tracing::info;
+
+use crate::database::{Database, COLUMN_FAMILIES, SCHEMA_VERSION_KEY};
+
+/// Version of export format, which is bumped on incompatible changes.
+const VERSION: u32 = 1;
+
+/// First line of an export.
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    exported_at: String,
+}
+
+/// One key of a column family, every line after header is one.
+#[derive(Debug, Serialize, Deserialize)]
+struct Record {
+    cf: String,
+    key: Bytes,
+    value: Bytes,
+}
+
+/// Bytes saved as text if they're UTF-8, otherwise as base64.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Bytes {
+    Text(String),
+    Base64 { base64: String },
+}
+
+/// Writes all column families to JSON Lines, returns number of records written.
+pub(crate) fn export(database: &Database, mut writer: impl Write) -> Result<usize> {
+    let header = Header {
+        version: VERSION,
+        exported_at: Utc::now().to_rfc3339(),
+    };
+    writeln!(writer, "{}", serde_json::to_string(&header)?)?;
+
+    let mut count = 0;
+    for cf in COLUMN_FAMILIES {
+        for entry in database.iterator_cf(cf)? {
+            let (key, value) = entry?;
+            let record = Record {
+                cf: cf.into(),
+                key: Bytes::new(&key),
+                value: Bytes::new(&value),
+            };
+            writeln!(writer, "{}", serde_json::to_string(&record)?)?;
+            count += 1;
+        }
+    }
+    writer.flush()?;
+    info!("Exported {count} records.");
+    Ok(count)
+}
+
+/// Restores column families from JSON Lines written by `export`, returns number of records read.
+/// Existing keys are overwritten, other keys are left as they are.
+///
+/// Imported data is upgraded to the latest schema version, exports written before
+/// schema versioning have no version.
+pub(crate) fn import(database: &Database, reader: impl BufRead) -> Result<usize> {
+    let mut lines = reader.lines();
+    let header: Header = match lines.next() {
+        Some(line) => serde_json::from_str(&line?).with_context(|| "Invalid export header")?,
+        None => bail!("Empty export"),
+    };
+    if header.version != VERSION {
+        bail!(
+            "Unsupported export version: {}, expected {VERSION}",
+            header.version
+        );
+    }
+
+    let mut count = 0;
+    let mut schema_version = None;
+    for (n, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        // Line numbers start from 1, after header.
+        let context = || format!("Invalid record at line {}", n + 2);
+        let record: Record = serde_json::from_str(&line).with_context(context)?;
+        if !COLUMN_FAMILIES.contains(&record.cf.as_str()) {
+            bail!("Unknown column family at line {}: {}", n + 2, record.cf);
+        }
+        let key = record.key.into_bytes().with_context(context)?;
+        let value = record.value.into_bytes().with_context(context)?;
+        if record.cf == "meta" && key == SCHEMA_VERSION_KEY.as_bytes() {
+            schema_version = Some(value);
+            continue;
+        }
+        database.put_cf(&record.cf, key, value)?;
+        count += 1;
+    }
+    let schema_version = schema_version.unwrap_or_else(|| b"0".to_vec());
+    database.put_cf("meta", SCHEMA_VERSION_KEY, schema_version)?;
+    database.migrate()?;
+    info!("Imported {count} records.");
+    Ok(count)
+}
+
+impl Bytes {
+    fn new(bytes: &[u8]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => Self::Text(text.into()),
+            Err(_) => Self::Base64 {
+                base64: STANDARD.encode(bytes),
+            },
+        }
+    }
+
+    fn into_bytes(self) -> Result<Vec<u8>> {
+        match self {
+            Self::Text(text) => Ok(text.into_bytes()),
+            Self::Base64 { base64 } => Ok(STANDARD.decode(base64)?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{export, import};
+    use crate::{database::Database, twitter::Tweet};
+
+    #[test]
+    fn export_and_import() {
+        let database = Database::memory();
+        database
+            .put_cf("state", "TwitterDev", "2024-01-01T00:00:00Z")
+            .unwrap();
+        // A tweet queued before schema versioning.
+        let tweet = r#"{"id":"1001","created_at":"2024-01-01T00:00:00Z","text":"Hi"}"#;
+        database
+            .put_cf("timeline", "TwitterDev:1001", tweet)
+            .unwrap();
+        database.put_cf("thread", "binary", [0xff, 0x00]).unwrap();
+        database
+            .put_cf(
+                "delivery",
+                "TwitterDev:1001/discord:xyz",
+                "2024-01-02T00:00:00Z",
+            )
+            .unwrap();
+
+        let mut jsonl = vec![];
+        assert_eq!(4, export(&database, &mut jsonl).unwrap());
+        let jsonl = String::from_utf8(jsonl).unwrap();
+        let lines: Vec<&str> = jsonl.lines().collect();
+        assert!(lines[0].starts_with(r#"{"version":1,"#));
+        assert_eq!(
+            r#"{"cf":"timeline","key":"TwitterDev:1001","value":"{\"id\":\"1001\",\"created_at\":\"2024-01-01T00:00:00Z\",\"text\":\"Hi\"}"}"#,
+            lines[1]
+        );
+        assert_eq!(
+            r#"{"cf":"thread","key":"binary","value":{"base64":"/wA="}}"#,
+            lines[4]
+        );
+
+        let restored = Database::memory();
+        assert_eq!(4, import(&restored, jsonl.as_bytes()).unwrap());
+        assert_eq!(
+            Some(b"2024-01-01T00:00:00Z".to_vec()),
+            restored.get_cf("state", "TwitterDev").unwrap()
+        );
+        assert_eq!(
+            Some(vec![0xff, 0x00]),
+            restored.get_cf("thread", "binary").unwrap()
+        );
+        // Imported data is migrated.
+        let value = restored.get_cf("timeline", "TwitterDev:1001").unwrap();
+        let tweet: Tweet = serde_json::from_slice(&value.unwrap()).unwrap();
+        assert_eq!(Some("2024-01-01T00:00:00Z"), tweet.queued_at());
+        assert_eq!(
+            Some(b"2024-01-02T00:00:00Z".to_vec()),
+            restored
+                .get_cf("delivery", "TwitterDev:1001/discord:xyz")
+                .unwrap()
+        );
+
+        let future = r#"{"version":2,"exported_at":"2024-01-01T00:00:00Z"}"#;
+        assert!(import(&restored, future.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use tracing::info;
+
+use crate::{
+    archive::Receipt,
+    config::PushConfig,
+    database::{Database, WriteBatch},
+    twitter::TwitterArchive,
+};
+
+/// Queues tweets of a Twitter archive in timeline, to be pushed to one route only.
+///
+/// Tweets are queued under the archive's account like polled ones, so other routes
+/// from the same account would push them too. They're marked as delivered to those
+/// routes, returns number of tweets queued. Tweets which are queued already, like by
+/// poll, are left as they are, so that every route still pushes them.
+pub(crate) fn import_archive(
+    database: &Database,
+    archive: TwitterArchive,
+    username: Option<String>,
+    route: &str,
+    push_config: &[PushConfig],
+) -> Result<usize> {
+    let Some(username) = username.or(archive.username) else {
+        bail!("No account username in Twitter archive, please specify it");
+    };
+    let destinations: Vec<String> = push_config
+        .iter()
+        .filter(|cfg| cfg.from == username)
+        .map(|cfg| cfg.destination.id())
+        .collect();
+    if !destinations.iter().any(|destination| destination == route) {
+        bail!("No included push route from {username} to {route} in config");
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let skipped = Receipt::skipped(&now).to_vec()?;
+    // Tweets are queued along with their receipts at once, or not at all.
+    let mut batch = WriteBatch::default();
+    let mut count = 0;
+    let mut queued = 0;
+    for tweet in archive.tweets {
+        let key = format!("{username}:{}", tweet.id);
+        if database.get_cf("timeline", &key)?.is_some() {
+            queued += 1;
+            continue;
+        }
+        let value = serde_json::to_vec(&tweet.queued(&now))
+            .with_context(|| "could not serialize tweet data to json")?;
+        batch.put_cf("timeline", &key, value);
+        for destination in destinations
+            .iter()
+            .filter(|&destination| destination != route)
+        {
+            batch.put_cf("delivery", format!("{key}/{destination}"), &skipped);
+        }
+        count += 1;
+    }
+    database.write(batch)?;
+    info!(
+        "Queued {count} tweets of {username} from Twitter archive for {route}, skipped {queued} tweets queued already."
+    );
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::import_archive;
+    use crate::{
+        config::{Config, PushConfig},
+        database::Database,
+        twitter::{archive_zip, Tweet, TwitterArchive},
+    };
+
+    fn push_config() -> Vec<PushConfig> {
+        let toml_str = r#"
+        rocksdb_path = "rocksdb"
+
+        [[push]]
+        included = true
+        from = "TwitterDev"
+        username = "@old_channel"
+
+        [[push]]
+        included = true
+        from = "TwitterDev"
+        username = "@new_channel"
+        "#;
+        toml::from_str::<Config>(toml_str).unwrap().push.unwrap()
+    }
+
+    fn archive() -> TwitterArchive {
+        TwitterArchive::read(Cursor::new(archive_zip())).unwrap()
+    }
+
+    #[test]
+    fn queue_for_route() {
+        let push_config = push_config();
+        let database = Database::memory();
+        let route = "telegram:@new_channel";
+        assert!(import_archive(
+            &database,
+            archive(),
+            None,
+            "telegram:@nowhere",
+            &push_config
+        )
+        .is_err());
+        assert!(import_archive(
+            &database,
+            archive(),
+            Some("jack".into()),
+            route,
+            &push_config
+        )
+        .is_err());
+        assert_eq!(
+            2,
+            import_archive(&database, archive(), None, route, &push_config).unwrap()
+        );
+
+        let key = "TwitterDev:1050118621198921728";
+        let value = database.get_cf("timeline", key).unwrap().unwrap();
+        // Imported tweets age from now, rather than from when they're created.
+        let tweet: Tweet = serde_json::from_slice(&value).unwrap();
+        assert_ne!(Some(tweet.created_at.as_str()), tweet.queued_at());
+        assert!(database
+            .get_cf("delivery", format!("{key}/telegram:@old_channel"))
+            .unwrap()
+            .is_some());
+        assert!(database
+            .get_cf("delivery", format!("{key}/{route}"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn skip_queued_tweets() {
+        let push_config = push_config();
+        let database = Database::memory();
+        // A tweet of the archive which is polled, and not pushed yet.
+        let key = "TwitterDev:1050118621198921729";
+        let polled = r#"{"id":"1050118621198921729","created_at":"2018-10-10T20:20:00.000Z","text":"@TwitterDev Indeed","queued_at":"2024-01-01T00:00:00Z"}"#;
+        database.put_cf("timeline", key, polled).unwrap();
+
+        let route = "telegram:@new_channel";
+        assert_eq!(
+            1,
+            import_archive(&database, archive(), None, route, &push_config).unwrap()
+        );
+        assert_eq!(
+            Some(polled.as_bytes().to_vec()),
+            database.get_cf("timeline", key).unwrap()
+        );
+        assert!(database
+            .get_cf("delivery", format!("{key}/telegram:@old_channel"))
+            .unwrap()
+            .is_none());
+        assert!(database
+            .get_cf(
+                "delivery",
+                "TwitterDev:1050118621198921728/telegram:@old_channel"
+            )
+            .unwrap()
+            .is_some());
+    }
+}
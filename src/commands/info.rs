@@ -1,55 +1,433 @@
-use std::str;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt::Write, str};
 use tabled::{Table, Tabled};
 
-use crate::{database::Database, twitter::Tweet};
+use crate::{
+    database::Database,
+    queue::{self, Limits},
+    twitter::Tweet,
+};
 
-pub(crate) fn info(database: &Database) -> anyhow::Result<()> {
-    display_state(database)?;
-    display_timeline(database)
+/// Filters and output format of info command.
+#[derive(Debug, Default, Clone, Args)]
+pub struct InfoOptions {
+    /// Only show this Twitter user
+    #[arg(long, value_name = "USERNAME")]
+    pub user: Option<String>,
+
+    /// Only show tweets created at or after this RFC3339 time
+    #[arg(long, value_name = "TIME")]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Only show tweets created before this RFC3339 time
+    #[arg(long, value_name = "TIME")]
+    pub until: Option<DateTime<Utc>>,
+
+    /// Show at most this many queued tweets
+    #[arg(long, value_name = "N")]
+    pub limit: Option<usize>,
+
+    /// Only show per-user queue depth, without listing tweets
+    #[arg(long)]
+    pub count_only: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t)]
+    pub format: InfoFormat,
+}
+
+/// Output format of info command.
+#[derive(Debug, Default, Clone, Copy, PartialEq, ValueEnum)]
+pub enum InfoFormat {
+    #[default]
+    Table,
+    /// One JSON document.
+    Json,
+    /// One JSON object per line, tagged by its section.
+    Jsonl,
+    /// One CSV table per section, separated by empty lines.
+    Csv,
+}
+
+pub(crate) fn info(database: &Database, limits: &Limits, options: &InfoOptions) -> Result<()> {
+    let overview = Overview::read(database, limits, options)?;
+    print!("{}", overview.render(options.format)?);
+    Ok(())
+}
+
+/// Data read from database, in sections.
+#[derive(Debug, Default, Serialize)]
+struct Overview {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state: Option<Vec<StateInfo>>,
+    queues: Vec<QueueInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limits: Option<Vec<LimitInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tweets: Option<Vec<TweetInfo>>,
+}
+
+/// Last polled tweet of a Twitter user, from column family state.
+#[derive(Debug, Tabled, Serialize)]
+struct StateInfo {
+    twitter_username: String,
+    last_tweet_datetime: String,
+}
+
+/// Tweets of a Twitter user queued in column family timeline.
+#[derive(Debug, Tabled, Serialize)]
+struct QueueInfo {
+    twitter_username: String,
+    queued: usize,
+    oldest: String,
+    newest: String,
+}
+
+/// Usage of a queue limit in config, regardless of filters.
+#[derive(Debug, Tabled, Serialize)]
+struct LimitInfo {
+    limit: String,
+    used: String,
+    max: String,
+}
+
+/// A tweet queued in column family timeline.
+#[derive(Debug, Tabled, Serialize)]
+struct TweetInfo {
+    key: String,
+    created_at: String,
+    text: String,
 }
 
-fn display_state(database: &Database) -> anyhow::Result<()> {
-    println!("Data in column family state:");
-    let mut overview = vec![];
-    for entry in database.iterator_cf("state").unwrap() {
+impl Overview {
+    fn read(database: &Database, limits: &Limits, options: &InfoOptions) -> Result<Self> {
+        let mut overview = Self::default();
+        if !options.count_only {
+            overview.state = Some(read_state(database, options)?);
+            overview.tweets = Some(vec![]);
+        }
+        // Usage of the whole timeline, which limits apply to.
+        let mut queued_per_user: BTreeMap<String, usize> = BTreeMap::new();
+        // Time the earliest queued tweet is queued at, which `max_age` applies to.
+        let mut earliest_queued: Option<DateTime<Utc>> = None;
+        let mut total_bytes = 0;
+
+        // Queue statistics of each user, ordered by username.
+        let mut queues: BTreeMap<String, (usize, DateTime<Utc>, DateTime<Utc>)> = BTreeMap::new();
+        for entry in database.iterator_cf("timeline")? {
+            let (key, value) = entry?;
+            total_bytes += queue::size(&key, &value);
+            let key = str::from_utf8(&key)?;
+            // Unwrap it directly since we're sure it's Some(&str).
+            let (username, _) = key.split_once(':').unwrap();
+            let tweet: Tweet = serde_json::from_slice(&value)?;
+            let created_at = DateTime::parse_from_rfc3339(&tweet.created_at)
+                .with_context(|| format!("Invalid creation time of tweet {key}"))?
+                .to_utc();
+            let queued_at = tweet
+                .queued_at()
+                .with_context(|| format!("No queue time of tweet {key}"))?;
+            let queued_at = DateTime::parse_from_rfc3339(queued_at)
+                .with_context(|| format!("Invalid queue time of tweet {key}"))?
+                .to_utc();
+            *queued_per_user.entry(username.into()).or_default() += 1;
+            earliest_queued =
+                Some(earliest_queued.map_or(queued_at, |earliest| earliest.min(queued_at)));
+
+            if options.user.as_deref().is_some_and(|user| user != username) {
+                continue;
+            }
+            if options.since.is_some_and(|since| created_at < since)
+                || options.until.is_some_and(|until| created_at >= until)
+            {
+                continue;
+            }
+
+            let queue = queues
+                .entry(username.into())
+                .or_insert((0, created_at, created_at));
+            queue.0 += 1;
+            queue.1 = queue.1.min(created_at);
+            queue.2 = queue.2.max(created_at);
+
+            if let Some(tweets) = &mut overview.tweets {
+                if options.limit.is_none_or(|limit| tweets.len() < limit) {
+                    tweets.push(TweetInfo {
+                        key: key.into(),
+                        created_at: tweet.created_at,
+                        text: tweet.text,
+                    });
+                }
+            }
+        }
+        overview.queues = queues
+            .into_iter()
+            .map(|(twitter_username, (queued, oldest, newest))| QueueInfo {
+                twitter_username,
+                queued,
+                oldest: oldest.to_rfc3339(),
+                newest: newest.to_rfc3339(),
+            })
+            .collect();
+
+        let mut usage = vec![];
+        if let Some(max) = limits.max_queue_per_user {
+            let used = queued_per_user
+                .iter()
+                .max_by_key(|(_, queued)| **queued)
+                .map_or("0".into(), |(username, queued)| {
+                    format!("{queued} ({username})")
+                });
+            usage.push(LimitInfo {
+                limit: "max_queue_per_user".into(),
+                used,
+                max: max.to_string(),
+            });
+        }
+        if let Some(max) = limits.max_age {
+            let age = earliest_queued.map_or(Duration::zero(), |earliest| Utc::now() - earliest);
+            usage.push(LimitInfo {
+                limit: "max_age".into(),
+                used: interval(age),
+                max: interval(max),
+            });
+        }
+        if let Some(max) = limits.max_total_bytes {
+            usage.push(LimitInfo {
+                limit: "max_total_bytes".into(),
+                used: total_bytes.to_string(),
+                max: max.to_string(),
+            });
+        }
+        overview.limits = (!usage.is_empty()).then_some(usage);
+        Ok(overview)
+    }
+
+    fn render(&self, format: InfoFormat) -> Result<String> {
+        let mut output = String::new();
+        match format {
+            InfoFormat::Table => {
+                if let Some(state) = &self.state {
+                    writeln!(output, "Data in column family state:")?;
+                    writeln!(output, "{}\n", Table::new(state))?;
+                }
+                writeln!(output, "Queued tweets per user:")?;
+                writeln!(output, "{}", Table::new(&self.queues))?;
+                if let Some(limits) = &self.limits {
+                    writeln!(output, "\nQueue usage against limits:")?;
+                    writeln!(output, "{}", Table::new(limits))?;
+                }
+                if let Some(tweets) = &self.tweets {
+                    writeln!(output, "\nData in column family timeline:")?;
+                    writeln!(output, "{}", Table::new(tweets))?;
+                }
+            }
+            InfoFormat::Json => writeln!(output, "{}", serde_json::to_string_pretty(self)?)?,
+            InfoFormat::Jsonl => {
+                let state = self
+                    .state
+                    .iter()
+                    .flatten()
+                    .map(|row| ("state", serde_json::to_value(row)));
+                let queues = self
+                    .queues
+                    .iter()
+                    .map(|row| ("queue", serde_json::to_value(row)));
+                let limits = self
+                    .limits
+                    .iter()
+                    .flatten()
+                    .map(|row| ("limit", serde_json::to_value(row)));
+                let tweets = self
+                    .tweets
+                    .iter()
+                    .flatten()
+                    .map(|row| ("tweet", serde_json::to_value(row)));
+                for (section, row) in state.chain(queues).chain(limits).chain(tweets) {
+                    let mut row = row?;
+                    row["type"] = section.into();
+                    writeln!(output, "{row}")?;
+                }
+            }
+            InfoFormat::Csv => {
+                let mut sections = vec![];
+                if let Some(state) = &self.state {
+                    sections.push(csv(state));
+                }
+                sections.push(csv(&self.queues));
+                if let Some(limits) = &self.limits {
+                    sections.push(csv(limits));
+                }
+                if let Some(tweets) = &self.tweets {
+                    sections.push(csv(tweets));
+                }
+                output = sections.join("\n");
+            }
+        }
+        Ok(output)
+    }
+}
+
+fn read_state(database: &Database, options: &InfoOptions) -> Result<Vec<StateInfo>> {
+    let mut state = vec![];
+    for entry in database.iterator_cf("state")? {
         let (key, value) = entry?;
         let key_str = str::from_utf8(&key)?;
+        if options.user.as_deref().is_some_and(|user| user != key_str) {
+            continue;
+        }
         let value_str = str::from_utf8(&value)?;
-        overview.push(StateInfo {
+        state.push(StateInfo {
             twitter_username: key_str.into(),
             last_tweet_datetime: value_str.into(),
         });
     }
-    println!("{}", Table::new(overview));
-    Ok(())
+    Ok(state)
 }
 
-fn display_timeline(database: &Database) -> anyhow::Result<()> {
-    // Start with en empty line.
-    println!("\nData in column family timeline:");
-    let timeline = database.iterator_cf("timeline").unwrap();
-    for entry in timeline {
-        let (key, value) = entry?;
-        let key_str = str::from_utf8(&key)?;
-        let value_str: Tweet = serde_json::from_slice(&value)?;
-        println!("  {key_str} = {value_str:?}");
+/// Formats a duration in days, hours, minutes and seconds, for example, "1d2h".
+fn interval(duration: Duration) -> String {
+    let units = [
+        (duration.num_days(), "d"),
+        (duration.num_hours() % 24, "h"),
+        (duration.num_minutes() % 60, "m"),
+        (duration.num_seconds() % 60, "s"),
+    ];
+    let interval: String = units
+        .iter()
+        .filter(|(amount, _)| *amount > 0)
+        .map(|(amount, unit)| format!("{amount}{unit}"))
+        .collect();
+    if interval.is_empty() {
+        "0s".into()
+    } else {
+        interval
     }
-    Ok(())
 }
 
-#[derive(Tabled)]
-struct StateInfo {
-    twitter_username: String,
-    last_tweet_datetime: String,
+/// Renders rows as a CSV table with a header line.
+fn csv<T: Tabled>(rows: &[T]) -> String {
+    let line = |fields: Vec<std::borrow::Cow<'_, str>>| {
+        fields
+            .iter()
+            .map(|field| {
+                if field.contains([',', '"', '\n', '\r']) {
+                    format!("\"{}\"", field.replace('"', "\"\""))
+                } else {
+                    field.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+            + "\n"
+    };
+    let mut output = line(T::headers());
+    for row in rows {
+        output.push_str(&line(row.fields()));
+    }
+    output
 }
 
 #[cfg(test)]
 mod tests {
-    use reqwest::Client;
-    use rocksdb::{Options, DB};
+    use reqwest_middleware::ClientWithMiddleware as Client;
+    use url::Url;
 
-    use super::info;
-    use crate::{commands::Poll, config::PollConfig, database::Database};
+    use super::{info, InfoFormat, InfoOptions, Overview};
+    use crate::{
+        commands::Poll, config::PollConfig, database::Database, queue::Limits,
+        twitter::API_ENDPOINT_BASE,
+    };
+
+    #[test]
+    fn filter() {
+        let database = Database::memory();
+        database
+            .put_cf("state", "TwitterDev", "2024-01-03T00:00:00Z")
+            .unwrap();
+        database
+            .put_cf("state", "jack", "2024-01-01T00:00:00Z")
+            .unwrap();
+        for (key, created_at, text) in [
+            ("TwitterDev:1001", "2024-01-01T00:00:00Z", "First"),
+            (
+                "TwitterDev:1002",
+                "2024-01-02T00:00:00Z",
+                "Second, quoted \"text\"",
+            ),
+            ("TwitterDev:1003", "2024-01-03T00:00:00Z", "Third"),
+            ("jack:2001", "2024-01-01T00:00:00Z", "Hello"),
+        ] {
+            let tweet = serde_json::json!({
+                "id": key,
+                "created_at": created_at,
+                "text": text,
+                "queued_at": created_at,
+            });
+            database.put_cf("timeline", key, tweet.to_string()).unwrap();
+        }
+
+        let options = InfoOptions {
+            user: Some("TwitterDev".into()),
+            since: Some("2024-01-02T00:00:00Z".parse().unwrap()),
+            limit: Some(1),
+            ..Default::default()
+        };
+        let overview = Overview::read(&database, &Limits::default(), &options).unwrap();
+        assert_eq!(1, overview.state.as_ref().unwrap().len());
+        assert_eq!(1, overview.queues.len());
+        assert_eq!(2, overview.queues[0].queued);
+        assert_eq!("2024-01-02T00:00:00+00:00", overview.queues[0].oldest);
+        assert_eq!("2024-01-03T00:00:00+00:00", overview.queues[0].newest);
+        assert_eq!(1, overview.tweets.as_ref().unwrap().len());
+        assert_eq!(
+            "twitter_username,last_tweet_datetime\n\
+             TwitterDev,2024-01-03T00:00:00Z\n\
+             \n\
+             twitter_username,queued,oldest,newest\n\
+             TwitterDev,2,2024-01-02T00:00:00+00:00,2024-01-03T00:00:00+00:00\n\
+             \n\
+             key,created_at,text\n\
+             TwitterDev:1002,2024-01-02T00:00:00Z,\"Second, quoted \"\"text\"\"\"\n",
+            overview.render(InfoFormat::Csv).unwrap()
+        );
+
+        let options = InfoOptions {
+            count_only: true,
+            ..Default::default()
+        };
+        let overview = Overview::read(&database, &Limits::default(), &options).unwrap();
+        assert_eq!(
+            "{\"newest\":\"2024-01-03T00:00:00+00:00\",\"oldest\":\"2024-01-01T00:00:00+00:00\",\"queued\":3,\"twitter_username\":\"TwitterDev\",\"type\":\"queue\"}\n\
+             {\"newest\":\"2024-01-01T00:00:00+00:00\",\"oldest\":\"2024-01-01T00:00:00+00:00\",\"queued\":1,\"twitter_username\":\"jack\",\"type\":\"queue\"}\n",
+            overview.render(InfoFormat::Jsonl).unwrap()
+        );
+
+        // Usage is of the whole timeline, regardless of filters.
+        let limits = Limits {
+            max_queue_per_user: Some(100),
+            max_age: Some(chrono::Duration::hours(36)),
+            max_total_bytes: Some(1_000_000),
+            ..Default::default()
+        };
+        let options = InfoOptions {
+            user: Some("jack".into()),
+            count_only: true,
+            ..Default::default()
+        };
+        let overview = Overview::read(&database, &limits, &options).unwrap();
+        let usage = overview.limits.unwrap();
+        assert_eq!("3 (TwitterDev)", usage[0].used);
+        assert_eq!("1d12h", usage[1].max);
+        assert_eq!(
+            ("max_total_bytes", "1000000"),
+            (usage[2].limit.as_str(), usage[2].max.as_str())
+        );
+        assert!(usage[2].used.parse::<u64>().unwrap() > 200);
+    }
 
     // To test this function:
     // RUST_LOG=debug cargo test get_info -- --ignored --show-output '[auth_token]'
@@ -59,9 +437,8 @@ mod tests {
         let mut args = std::env::args().rev();
         let auth_token = args.next();
 
-        let rocksdb_path = "test";
-        let database = Database::open(rocksdb_path);
-        let client = Client::new();
+        let database = Database::memory();
+        let client = Client::default();
         let poll_config = vec![PollConfig {
             included: true,
             username: "TwitterDev".into(),
@@ -71,11 +448,9 @@ mod tests {
             since_id: None,
         }];
 
-        let mut poll = Poll::new(auth_token, poll_config, &client, &database).unwrap();
+        let twitter_api = Url::parse(API_ENDPOINT_BASE).unwrap();
+        let mut poll = Poll::new(auth_token, twitter_api, poll_config, &client, &database).unwrap();
         poll.run().await.unwrap();
-        info(&database).unwrap();
-
-        drop(database);
-        DB::destroy(&Options::default(), rocksdb_path).unwrap();
+        info(&database, &Limits::default(), &InfoOptions::default()).unwrap();
     }
 }
@@ -1,19 +1,21 @@
 use anyhow::{anyhow, Context, Result};
-use chrono::{DateTime, Duration};
-use reqwest::Client;
+use chrono::{DateTime, Duration, Utc};
+use reqwest_middleware::ClientWithMiddleware as Client;
 use std::{collections::HashMap, str};
 use tracing::{info, trace};
 use url::Url;
 
 use crate::{
     config::PollConfig,
-    database::Database,
+    database::{Database, WriteBatch},
     twitter::{PaginationToken, Timeline, Tweet, UrlBuilder, Users},
 };
 
 /// Poll command entry.
 pub(crate) struct Poll<'a> {
     twitter_token: String,
+    /// Twitter API base URL.
+    twitter_api: Url,
     config: Vec<PollConfig>,
     client: &'a Client,
     database: &'a Database,
@@ -22,6 +24,7 @@ pub(crate) struct Poll<'a> {
 impl<'a> Poll<'a> {
     pub(crate) fn new(
         twitter_token: Option<String>,
+        twitter_api: Url,
         poll_config: Vec<PollConfig>,
         client: &'a Client,
         database: &'a Database,
@@ -29,6 +32,7 @@ impl<'a> Poll<'a> {
         let twitter_token = twitter_token.ok_or_else(|| anyhow!("Empty twitter token"))?;
         Ok(Self {
             twitter_token,
+            twitter_api,
             config: poll_config,
             client,
             database,
@@ -45,22 +49,30 @@ impl<'a> Poll<'a> {
             cfg.insert_start_time(start_time);
             info!("Polling timeline with config: {cfg:?}",);
 
-            let endpoint = Self::endpoint(cfg, &user_map)?;
+            let endpoint = Self::endpoint(&self.twitter_api, cfg, &user_map)?;
             // Note: `since_id` takes higher priority than `start_time` in request query parameters.
             let since_id = cfg.since_id.take().map(PaginationToken::TweetID);
             let mut timeline = Timeline::new(self.client, endpoint, &self.twitter_token, since_id);
+            // Tweets and state of the whole timeline are written at once, so that
+            // an interrupted poll leaves neither of them, and is retried next time.
+            let mut batch = WriteBatch::default();
+            let queued_at = Utc::now().to_rfc3339();
 
             // Poll first tweet. The first tweet is the latest one in timeline.
             // Extract `create_at` from tweet, and upsert it to persistent state.
             // So we can continually poll user's timeline from last time.
             if let Some(tweet) = timeline.try_next().await? {
-                Self::upsert_state(self.database, &cfg.username, &tweet.created_at)?;
-                Self::insert_tweet(self.database, &cfg.username, &tweet)?;
+                Self::upsert_state(&mut batch, &cfg.username, &tweet.created_at);
+                Self::insert_tweet(&mut batch, &cfg.username, tweet.queued(&queued_at))?;
             }
             // Poll remaining tweets.
             while let Some(tweet) = timeline.try_next().await? {
-                Self::insert_tweet(self.database, &cfg.username, &tweet)?;
+                Self::insert_tweet(&mut batch, &cfg.username, tweet.queued(&queued_at))?;
             }
+            // One of the writes is state, the others are tweets.
+            let count = batch.len().saturating_sub(1);
+            self.database.write(batch)?;
+            info!("Stored {count} tweets of {}.", cfg.username);
         }
         info!("Finished polling all timeline.");
         Ok(())
@@ -80,24 +92,31 @@ impl<'a> Poll<'a> {
         }
     }
 
-    fn upsert_state(database: &Database, username: &str, created_at: &str) -> Result<()> {
+    fn upsert_state(batch: &mut WriteBatch, username: &str, created_at: &str) {
         trace!("Upsert state: key: {username}, value: {created_at}");
-        database.put_cf("state", username, created_at)
+        batch.put_cf("state", username, created_at);
     }
 
-    fn insert_tweet(database: &Database, username: &str, tweet: &Tweet) -> Result<()> {
+    fn insert_tweet(batch: &mut WriteBatch, username: &str, tweet: Tweet) -> Result<()> {
         let key = format!("{username}:{}", tweet.id);
         let value =
             serde_json::to_vec(&tweet).with_context(|| "could not serialize tweet data to json")?;
         trace!("Insert tweet: key: {key}, value: {tweet:?}");
-        database.put_cf("timeline", key, value)
+        batch.put_cf("timeline", key, value);
+        Ok(())
     }
 
-    fn endpoint(config: &PollConfig, user_map: &HashMap<String, String>) -> Result<Url> {
+    fn endpoint(
+        twitter_api: &Url,
+        config: &PollConfig,
+        user_map: &HashMap<String, String>,
+    ) -> Result<Url> {
         // Unwrap it directly since we are sure it's not None.
         let user_id = user_map.get(config.username.as_str()).unwrap();
-        Ok(UrlBuilder::new(user_id)?
-            .tweet_fields(vec!["created_at"])
+        Ok(UrlBuilder::new(twitter_api, user_id)?
+            .tweet_fields(vec!["created_at", "referenced_tweets"])
+            .expansions(vec!["attachments.media_keys"])
+            .media_fields(vec!["url", "preview_image_url"])
             // Set default `max_results` value: 100.
             .max_results(config.max_results.unwrap_or(100))
             .start_time(config.start_time.as_deref())
@@ -112,7 +131,7 @@ impl<'a> Poll<'a> {
             .iter()
             .map(|cfg| cfg.username.as_str())
             .collect();
-        Users::fetch(client, usernames, &self.twitter_token)
+        Users::fetch(client, &self.twitter_api, usernames, &self.twitter_token)
             .await?
             .ok_or_else(|| anyhow!("No Twitter users found"))
     }
@@ -120,11 +139,11 @@ impl<'a> Poll<'a> {
 
 #[cfg(test)]
 mod tests {
-    use reqwest::Client;
-    use rocksdb::{Options, DB};
+    use reqwest_middleware::ClientWithMiddleware as Client;
+    use url::Url;
 
     use super::Poll;
-    use crate::{config::PollConfig, database::Database};
+    use crate::{config::PollConfig, database::Database, twitter::API_ENDPOINT_BASE};
 
     // To test this function:
     // RUST_LOG=debug cargo test poll -- --ignored '[auth_token]'
@@ -134,9 +153,9 @@ mod tests {
         let mut args = std::env::args().rev();
         let auth_token = args.next();
 
-        let rocksdb_path = "test";
-        let database = Database::open(rocksdb_path);
-        let client = Client::new();
+        let database = Database::memory();
+        let client = Client::default();
+        let twitter_api = Url::parse(API_ENDPOINT_BASE).unwrap();
         let mut poll_config = vec![PollConfig {
             included: true,
             username: "TwitterDev".into(),
@@ -146,8 +165,14 @@ mod tests {
             since_id: None,
         }];
         {
-            let mut poll =
-                Poll::new(auth_token.clone(), poll_config.clone(), &client, &database).unwrap();
+            let mut poll = Poll::new(
+                auth_token.clone(),
+                twitter_api.clone(),
+                poll_config.clone(),
+                &client,
+                &database,
+            )
+            .unwrap();
             poll.run().await.unwrap();
         }
         {
@@ -156,12 +181,10 @@ mod tests {
                 cfg.start_time.take();
                 cfg.end_time.replace("2022-12-01T00:00:00.000Z".into());
             });
-            let mut poll = Poll::new(auth_token, poll_config, &client, &database).unwrap();
+            let mut poll =
+                Poll::new(auth_token, twitter_api, poll_config, &client, &database).unwrap();
             // Poll again from last time.
             poll.run().await.unwrap();
         }
-
-        drop(database);
-        DB::destroy(&Options::default(), rocksdb_path).unwrap();
     }
 }
@@ -1,5 +1,11 @@
-use anyhow::{anyhow, Context, Result};
-use reqwest::{Client, StatusCode};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use clap::ValueEnum;
+use reqwest::{Response, StatusCode};
+use reqwest_middleware::ClientWithMiddleware as Client;
+use serde::Serialize;
+use serde_json::Value;
 use std::{collections::HashMap, str, time::Duration};
 use tokio::{
     signal::{
@@ -10,62 +16,146 @@ use tokio::{
     time,
 };
 use tracing::{debug, info, warn};
+use url::Url;
 
-use crate::{config::PushConfig, database::Database, telegram::Message, twitter::Tweet};
+use crate::{
+    archive::{self, Receipt, Retention},
+    config::{Destination, PushConfig, Quiet, QuietHours, Schedule},
+    database::{Database, WriteBatch},
+    dead_letter::DeadLetter,
+    discord, email, mastodon, matrix, slack,
+    telegram::{self, Message},
+    thread::{self, Thread},
+    twitter::Tweet,
+    webhook,
+};
 
 /// Push command entry.
 ///
-/// The `first_entry` and `last_entry` fields are used to mark
-/// entries range in timeline column family that successfully
-/// pushed to Telegram channel(s). So that we can delete them
-/// from database after pushing.
+/// A tweet routed to several destinations is recorded in delivery
+/// column family once a destination accepts it, so that an interrupted
+/// push doesn't deliver it to that destination again.
+///
+/// Tweets are deleted from timeline column family after pushing only
+/// when every destination has accepted them, or has permanently rejected
+/// them into dead_letter column family. Tweets waiting for a digest
+/// which is not due yet, or for quiet hours to end, stay in timeline until
+/// a later push.
 pub(crate) struct Push<'a> {
-    telegram_token: String,
+    telegram_token: Option<String>,
+    /// Telegram Bot API base URL.
+    telegram_api: Url,
+    /// Slack Web API base URL.
+    slack_api: Url,
     config: Vec<PushConfig>,
     client: &'a Client,
     database: &'a mut Database,
-    /// The first entry when reading timeline column family for pushing.
-    first_entry: Option<Box<[u8]>>,
-    /// The last entry when reading timeline column family for pushing.
-    last_entry: Option<Box<[u8]>>,
+    /// Keys of timeline entries that are pushed to all destinations.
+    pushed: Vec<Box<[u8]>>,
+    /// Whether all timeline is pushed, so that it can be dropped entirely.
+    finished: bool,
+    /// Print messages in this format instead of sending them, leaving database untouched.
+    dry_run: Option<PreviewFormat>,
+    /// Retention policy of archive which pushed tweets are moved to, or `None` to delete them.
+    archive: Option<Retention>,
+    /// How long Mastodon statuses are kept in thread column family for replies to find them.
+    thread_max_age: chrono::Duration,
     /// Shutdown signal.
     signal: Receiver<()>,
 }
 
+/// A destination that tweets are pushed to, along with when they are pushed.
+struct Route {
+    destination: Destination,
+    /// Digest schedule, or `None` if tweets are pushed one by one.
+    schedule: Option<Schedule>,
+    timezone: Tz,
+    quiet_hours: Option<QuietHours>,
+    quiet: Quiet,
+}
+
+/// How messages are printed in a dry run.
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum PreviewFormat {
+    /// Destination headers followed by pretty printed messages.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Messages which would be sent to a destination for one tweet, or for a digest.
+#[derive(Debug, Serialize)]
+struct Preview {
+    destination: String,
+    /// Timeline keys of tweets.
+    tweets: Vec<String>,
+    messages: Vec<Value>,
+}
+
+/// Tweets gathered for a digest route, along with their timeline keys.
+type DigestBatch<'d> = (&'d Route, Vec<(Box<[u8]>, String, Tweet)>);
+
 impl<'a> Push<'a> {
     pub(crate) fn new(
         telegram_token: Option<String>,
+        telegram_api: Url,
+        slack_api: Url,
         config: Vec<PushConfig>,
         client: &'a Client,
         database: &'a mut Database,
-    ) -> Result<Self> {
-        let telegram_token = telegram_token.ok_or_else(|| anyhow!("Empty Telegram token"))?;
+    ) -> Self {
         let signal = shutdown_signal();
-        Ok(Self {
+        Self {
             telegram_token,
+            telegram_api,
+            slack_api,
             config,
             client,
             database,
-            first_entry: None,
-            last_entry: None,
+            pushed: vec![],
+            finished: false,
+            dry_run: None,
+            archive: None,
+            thread_max_age: thread::DEFAULT_MAX_AGE,
             signal,
-        })
+        }
+    }
+
+    /// Keeps pushed tweets in archive column family instead of deleting them.
+    pub(crate) fn archive(mut self, retention: Retention) -> Self {
+        self.archive = Some(retention);
+        self
+    }
+
+    /// Keeps Mastodon statuses for replies to be threaded to for `max_age`, instead of the default.
+    pub(crate) fn thread_max_age(mut self, max_age: chrono::Duration) -> Self {
+        self.thread_max_age = max_age;
+        self
+    }
+
+    /// Walks timeline without sending anything, printing messages that would be sent.
+    pub(crate) fn dry_run(mut self, format: PreviewFormat) -> Self {
+        self.dry_run = Some(format);
+        self
     }
 
     pub(crate) async fn run(&mut self) -> Result<()> {
-        let user_map = self.user_map();
+        let user_map = self.user_map()?;
+        // Tweets gathered for each digest destination which is due.
+        let mut digests: HashMap<String, DigestBatch> = HashMap::new();
+        // Number of digest destinations each held tweet is waiting for.
+        let mut held: HashMap<Box<[u8]>, usize> = HashMap::new();
+        let mut completed = true;
         // Read timeline column family from database.
-        // Note: we're sure there's a timeline iterator, so just unwrap it directly.
-        for (i, entry) in self.database.iterator_cf("timeline").unwrap().enumerate() {
-            let (key, value) = entry?;
-            if i == 0 {
-                // Keep the first entry key.
-                self.first_entry = Some(key.clone());
-            }
+        'timeline: for key in timeline_keys(self.database)? {
+            let Some(value) = self.database.get_cf("timeline", &key)? else {
+                continue;
+            };
 
             // Check shutdown signal first.
             if self.signal.try_recv().is_ok() {
-                self.last_entry = Some(key);
+                completed = false;
                 break;
             }
 
@@ -77,65 +167,625 @@ impl<'a> Push<'a> {
                 (twitter_username, tweet)
             };
             debug!("Read {twitter_username}'s tweet.");
-            if let Some(telegram_channel) = user_map.get(twitter_username) {
-                debug!("Push tweet to {telegram_channel}");
-                let response = Message::new(telegram_channel, tweet)
-                    .send(self.client, &self.telegram_token)
+            let Some(destinations) = user_map.get(twitter_username) else {
+                self.pushed.push(key);
+                continue;
+            };
+            let mut waiting = 0;
+            for route in destinations {
+                let destination = &route.destination;
+                let destination_id = destination.id();
+                let delivery_key = [&key[..], b"/", destination_id.as_bytes()].concat();
+                if self.database.get_cf("delivery", &delivery_key)?.is_some() {
+                    debug!("Tweet already pushed to {destination_id}, skip it.");
+                    continue;
+                }
+                if self
+                    .database
+                    .get_cf("dead_letter", &delivery_key)?
+                    .is_some()
+                {
+                    debug!("Tweet is dead letter of {destination_id}, skip it.");
+                    continue;
+                }
+
+                let quiet = route.quiet_at(Utc::now());
+                if quiet == Some(Quiet::Hold) {
+                    debug!("Quiet hours of {destination_id}, hold tweet.");
+                    waiting += 1;
+                    continue;
+                }
+
+                if let Some(schedule) = &route.schedule {
+                    waiting += 1;
+                    if self.digest_due(&destination_id, schedule, route.timezone)? {
+                        digests
+                            .entry(destination_id)
+                            .or_insert_with(|| (route, vec![]))
+                            .1
+                            .push((key.clone(), twitter_username.into(), tweet.clone()));
+                    }
+                    continue;
+                }
+
+                debug!("Push tweet to {destination_id}");
+                let delivery = self
+                    .send(
+                        destination,
+                        twitter_username,
+                        tweet.clone(),
+                        quiet == Some(Quiet::Silent),
+                    )
                     .await
-                    .with_context(|| "Failed to send message to Telegram channel")
-                    .map_err(|err| {
-                        // This error check is necessary in order to tidy database despite error or panic.
-                        self.last_entry = Some(key.clone());
-                        err
-                    })?;
-                match response.status() {
-                    // Note: Telegram bot api applies requests rate limit.
-                    StatusCode::OK => time::sleep(Duration::from_secs(3)).await,
-                    other => {
+                    .with_context(|| format!("Failed to send message to {destination_id}"))?;
+                let message_id = match delivery {
+                    Delivery::Sent { message_id } => message_id,
+                    Delivery::Rejected { status, body }
+                        if matches!(destination, Destination::Telegram { .. })
+                            && telegram::is_permanent_error(status, &body) =>
+                    {
+                        let error = telegram::error_description(&body);
+                        warn!(
+                            "Tweet permanently rejected, moved to dead letter queue, destination: {destination_id}, response status: {status}, error: {error}"
+                        );
+                        let dead_letter =
+                            DeadLetter::new(tweet.clone(), status, error, &Utc::now().to_rfc3339());
+                        self.put_cf("dead_letter", delivery_key, dead_letter.to_vec()?)?;
+                        continue;
+                    }
+                    Delivery::Rejected { status, body } => {
                         warn!(
-                            "Request not successful, channel: {telegram_channel}, response status: {other}, body: {}",
-                            response.text().await.unwrap_or_else(|_| "".to_string())
+                            "Request not successful, destination: {destination_id}, response status: {status}, body: {body}"
                         );
-                        // Keep the last entry key.
-                        self.last_entry = Some(key);
-                        break;
+                        completed = false;
+                        break 'timeline;
                     }
+                };
+
+                if let (Destination::Mastodon { threads: true, .. }, Some(message_id)) =
+                    (destination, &message_id)
+                {
+                    // Remember the status published for this tweet, so replies to it can be threaded.
+                    let thread_key = format!("{destination_id}/{}", tweet.id);
+                    let thread = Thread::new(message_id.clone(), &Utc::now().to_rfc3339());
+                    self.put_cf("thread", thread_key, thread.to_vec()?)?;
+                }
+                let receipt = Receipt::new(message_id, &Utc::now().to_rfc3339());
+                self.put_cf("delivery", delivery_key, receipt.to_vec()?)?;
+                if let (Destination::Telegram { .. }, None) = (destination, self.dry_run) {
+                    // Note: Telegram bot api applies requests rate limit.
+                    time::sleep(Duration::from_secs(3)).await;
+                }
+            }
+            if waiting == 0 {
+                self.pushed.push(key);
+            } else {
+                held.insert(key, waiting);
+            }
+        }
+
+        // Only send complete digests, the rest of tweets would be left out of them otherwise.
+        if completed {
+            for (destination_id, (route, entries)) in digests {
+                debug!(
+                    "Send digest of {} tweets to {destination_id}",
+                    entries.len()
+                );
+                let tweets: Vec<(&str, Tweet)> = entries
+                    .iter()
+                    .map(|(_, username, tweet)| (username.as_str(), tweet.clone()))
+                    .collect();
+                let (sent, delivery) = self
+                    .send_digest(
+                        &route.destination,
+                        &tweets,
+                        route.quiet_at(Utc::now()) == Some(Quiet::Silent),
+                    )
+                    .await;
+
+                // Tweets of digest messages which are sent are delivered, even if a later message
+                // of the digest fails, so that they're not sent again with the rest of tweets.
+                let sent_at = Utc::now().to_rfc3339();
+                for (key, _, _) in entries.into_iter().take(sent) {
+                    let delivery_key = [&key[..], b"/", destination_id.as_bytes()].concat();
+                    let receipt = Receipt::new(None, &sent_at);
+                    self.put_cf("delivery", delivery_key, receipt.to_vec()?)?;
+                    // Unwrap it directly since every tweet in digests is held.
+                    let waiting = held.get_mut(&key).unwrap();
+                    *waiting -= 1;
+                    if *waiting == 0 {
+                        held.remove(&key);
+                        self.pushed.push(key);
+                    }
+                }
+
+                let delivery = delivery
+                    .with_context(|| format!("Failed to send digest to {destination_id}"))?;
+                if let Delivery::Rejected { status, body } = delivery {
+                    warn!(
+                        "Request not successful, destination: {destination_id}, response status: {status}, body: {body}"
+                    );
+                    continue;
                 }
+                self.put_cf("digest", &destination_id, &sent_at)?;
             }
         }
+        self.finished = completed && held.is_empty();
         Ok(())
     }
 
-    fn tidy_database(&mut self) -> Result<()> {
-        match (self.first_entry.take(), self.last_entry.take()) {
-            (Some(first_entry), Some(last_entry)) => {
-                info!("Push stopped, deleting pushed tweets in database.");
+    /// Checks whether a digest is due for a destination, given when the last digest was sent to it.
+    fn digest_due(&self, destination_id: &str, schedule: &Schedule, timezone: Tz) -> Result<bool> {
+        let last_sent = self
+            .database
+            .get_cf("digest", destination_id)?
+            .map(|sent_at| -> Result<_> {
+                Ok(DateTime::parse_from_rfc3339(str::from_utf8(&sent_at)?)?.to_utc())
+            })
+            .transpose()?;
+        Ok(schedule.is_due(last_sent, Utc::now().with_timezone(&timezone)))
+    }
+
+    async fn send(
+        &self,
+        destination: &Destination,
+        twitter_username: &str,
+        tweet: Tweet,
+        silent: bool,
+    ) -> Result<Delivery> {
+        if let Some(format) = self.dry_run {
+            self.preview(destination, &[(twitter_username, tweet)], silent, false)?
+                .print(format)?;
+            return Ok(Delivery::Sent { message_id: None });
+        }
+
+        // Each destination responds with ID of created message in a different place.
+        let (response, message_id) = match destination {
+            Destination::Telegram { username, options } => {
+                // Unwrap it directly since it's checked by `user_map`.
+                let telegram_token = self.telegram_token.as_deref().unwrap();
+                let response = Message::new(username, tweet)
+                    .options(options)
+                    .buttons(&options.buttons, twitter_username)
+                    .silent(silent)
+                    .send(self.client, &self.telegram_api, telegram_token)
+                    .await?;
+                (response, Some("/result/message_id"))
+            }
+            Destination::Discord { webhook_url } => {
+                let response = discord::Message::new(twitter_username, tweet)
+                    .send(self.client, webhook_url)
+                    .await?;
+                (response, Some("/id"))
+            }
+            Destination::Matrix {
+                homeserver,
+                room_id,
+                access_token,
+            } => {
+                let room = matrix::Room {
+                    homeserver,
+                    room_id,
+                    access_token,
+                };
+                let response = matrix::Message::new(twitter_username, tweet)
+                    .send(self.client, &room)
+                    .await?;
+                (response, Some("/event_id"))
+            }
+            Destination::Webhook {
+                url,
+                headers,
+                secret,
+            } => {
+                let webhook = webhook::Webhook {
+                    url,
+                    headers,
+                    secret: secret.as_deref(),
+                };
+                let response = webhook::Payload::new(twitter_username, tweet)
+                    .send(self.client, &webhook)
+                    .await?;
+                (response, None)
+            }
+            Destination::Slack {
+                webhook_url,
+                token,
+                channel,
+            } => {
+                let channel = match (webhook_url, token, channel) {
+                    (Some(webhook_url), _, _) => slack::Channel::Webhook(webhook_url),
+                    // Unwrap them directly since they're checked by `user_map`.
+                    (None, token, channel) => slack::Channel::Bot {
+                        api: &self.slack_api,
+                        token: token.as_deref().unwrap(),
+                        channel: channel.as_deref().unwrap(),
+                    },
+                };
+                let response = slack::Message::new(twitter_username, tweet)
+                    .send(self.client, channel)
+                    .await?;
+                // Only `chat.postMessage` responds with timestamp ID of the message.
+                (response, Some("/ts"))
+            }
+            Destination::Mastodon {
+                instance,
+                access_token,
+                visibility,
+                threads,
+            } => {
+                let in_reply_to_id = self.in_reply_to_id(destination, *threads, &tweet)?;
+                let account = mastodon::Account {
+                    instance,
+                    access_token,
+                };
+                let response =
+                    mastodon::Status::new(twitter_username, tweet, *visibility, in_reply_to_id)
+                        .send(self.client, &account)
+                        .await?;
+                (response, Some("/id"))
+            }
+            Destination::Email { .. } => {
+                self.send_email(destination, &[(twitter_username, tweet)], false)
+                    .await?;
+                return Ok(Delivery::Sent { message_id: None });
+            }
+        };
+        Ok(Delivery::from_response(response, message_id).await)
+    }
+
+    /// Sends a digest of many tweets, destinations without digest support are rejected by `user_map`.
+    ///
+    /// A digest may be split into several messages, so it returns how many of the tweets, in order,
+    /// are sent before the digest is completely sent, rejected or failed.
+    async fn send_digest(
+        &self,
+        destination: &Destination,
+        tweets: &[(&str, Tweet)],
+        silent: bool,
+    ) -> (usize, Result<Delivery>) {
+        if let Some(format) = self.dry_run {
+            let printed = self
+                .preview(destination, tweets, silent, true)
+                .and_then(|preview| preview.print(format));
+            return (
+                tweets.len(),
+                printed.map(|_| Delivery::Sent { message_id: None }),
+            );
+        }
+
+        match destination {
+            Destination::Telegram { username, options } => {
+                // Unwrap it directly since it's checked by `user_map`.
+                let telegram_token = self.telegram_token.as_deref().unwrap();
+                let mut sent = 0;
+                for (i, (message, count)) in
+                    Message::digest(username, tweets).into_iter().enumerate()
+                {
+                    if i > 0 {
+                        // Note: Telegram bot api applies requests rate limit.
+                        time::sleep(Duration::from_secs(3)).await;
+                    }
+                    let response = message
+                        .options(options)
+                        .silent(silent)
+                        .send(self.client, &self.telegram_api, telegram_token)
+                        .await;
+                    let delivery = match response {
+                        Ok(response) => Delivery::from_response(response, None).await,
+                        Err(e) => return (sent, Err(e)),
+                    };
+                    if let Delivery::Rejected { .. } = delivery {
+                        return (sent, Ok(delivery));
+                    }
+                    sent += count;
+                }
+                (sent, Ok(Delivery::Sent { message_id: None }))
+            }
+            Destination::Email { .. } => match self.send_email(destination, tweets, true).await {
+                Ok(()) => (tweets.len(), Ok(Delivery::Sent { message_id: None })),
+                Err(e) => (0, Err(e)),
+            },
+            _ => (
+                0,
+                Err(anyhow!(
+                    "Digest mode is not supported by {}",
+                    destination.id()
+                )),
+            ),
+        }
+    }
+
+    /// Sends an email of one tweet, or a digest of many tweets.
+    async fn send_email(
+        &self,
+        destination: &Destination,
+        tweets: &[(&str, Tweet)],
+        digest: bool,
+    ) -> Result<()> {
+        let Destination::Email {
+            host,
+            port,
+            tls,
+            user,
+            password,
+            sender,
+            recipients,
+            subject,
+        } = destination
+        else {
+            bail!("Not an email destination: {}", destination.id());
+        };
+        let mailer = email::Mailer {
+            host,
+            port: *port,
+            tls: *tls,
+            credentials: user.as_deref().zip(password.as_deref()),
+        };
+        email::Email::new(email_subject(subject, digest), tweets)
+            .send(&mailer, sender, recipients)
+            .await
+    }
+
+    /// Renders messages that would be sent to a destination, without sending them.
+    fn preview(
+        &self,
+        destination: &Destination,
+        tweets: &[(&str, Tweet)],
+        silent: bool,
+        digest: bool,
+    ) -> Result<Preview> {
+        let (from, tweet) = tweets.first().cloned().context("Nothing to preview")?;
+        let messages = match destination {
+            Destination::Telegram { username, options } if digest => {
+                Message::digest(username, tweets)
+                    .into_iter()
+                    .map(|(message, _)| {
+                        serde_json::to_value(message.options(options).silent(silent))
+                    })
+                    .collect::<Result<_, _>>()?
+            }
+            Destination::Telegram { username, options } => to_value(
+                Message::new(username, tweet)
+                    .options(options)
+                    .buttons(&options.buttons, from)
+                    .silent(silent),
+            )?,
+            Destination::Email { subject, .. } => {
+                to_value(email::Email::new(email_subject(subject, digest), tweets))?
+            }
+            _ if digest => bail!("Digest mode is not supported by {}", destination.id()),
+            Destination::Discord { .. } => to_value(discord::Message::new(from, tweet))?,
+            Destination::Matrix { .. } => to_value(matrix::Message::new(from, tweet))?,
+            Destination::Webhook { .. } => to_value(webhook::Payload::new(from, tweet))?,
+            Destination::Slack { .. } => to_value(slack::Message::new(from, tweet))?,
+            Destination::Mastodon {
+                visibility,
+                threads,
+                ..
+            } => {
+                let in_reply_to_id = self.in_reply_to_id(destination, *threads, &tweet)?;
+                to_value(mastodon::Status::new(
+                    from,
+                    tweet,
+                    *visibility,
+                    in_reply_to_id,
+                ))?
+            }
+        };
+        Ok(Preview {
+            destination: destination.id(),
+            tweets: tweets
+                .iter()
+                .map(|(username, tweet)| format!("{username}:{}", tweet.id))
+                .collect(),
+            messages,
+        })
+    }
+
+    /// Looks up the Mastodon status published for the tweet that a tweet replies to.
+    fn in_reply_to_id(
+        &self,
+        destination: &Destination,
+        threads: bool,
+        tweet: &Tweet,
+    ) -> Result<Option<String>> {
+        match tweet.in_reply_to() {
+            Some(in_reply_to) if threads => {
+                let thread_key = format!("{}/{in_reply_to}", destination.id());
                 self.database
-                    .delete_range_cf("timeline", first_entry, last_entry)
+                    .get_cf("thread", thread_key)?
+                    .map(|value| Ok(Thread::from_slice(&value)?.status_id))
+                    .transpose()
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Writes to database, unless it's a dry run.
+    fn put_cf<K, V>(&self, cf: &str, key: K, value: V) -> Result<()>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        match self.dry_run {
+            Some(_) => Ok(()),
+            None => self.database.put_cf(cf, key, value),
+        }
+    }
+
+    fn tidy_database(&mut self) -> Result<()> {
+        let now = Utc::now();
+        if let Some(retention) = &self.archive {
+            for key in &self.pushed {
+                archive::archive(self.database, key, now)?;
+            }
+            archive::prune(self.database, retention, now)?;
+        }
+        thread::prune(self.database, self.thread_max_age, now)?;
+        if self.pushed.is_empty() {
+            info!("No tweets to push.");
+            return Ok(());
+        }
+        if self.finished {
+            info!("Finished pushing all timeline.");
+            self.database.clear_cf("delivery")?;
+            return self.database.clear_cf("timeline");
+        }
+
+        info!("Push stopped, deleting pushed tweets in database.");
+        let mut batch = WriteBatch::default();
+        for key in self.pushed.drain(..) {
+            // Delivery keys of a tweet are prefixed by its timeline key and a slash.
+            let from = [&key[..], b"/"].concat();
+            let to = [&key[..], b"0"].concat();
+            batch.delete_range_cf("delivery", from, to);
+            batch.delete_cf("timeline", key);
+        }
+        self.database.write(batch)
+    }
+
+    /// Returns a Twitter username to routes map.
+    fn user_map(&mut self) -> Result<HashMap<String, Vec<Route>>> {
+        // A dry run only renders messages, which needs no token.
+        let pushes_to_telegram = self
+            .config
+            .iter()
+            .any(|cfg| matches!(cfg.destination, Destination::Telegram { .. }));
+        if pushes_to_telegram && self.telegram_token.is_none() && self.dry_run.is_none() {
+            bail!("Empty Telegram token");
+        }
+
+        let mut user_map: HashMap<String, Vec<_>> = HashMap::new();
+        for cfg in self.config.drain(..) {
+            let destination_id = cfg.destination.id();
+            let context = || format!("Invalid push config of {destination_id}");
+            let route = Route {
+                schedule: cfg.schedule().with_context(context)?,
+                timezone: cfg.timezone().with_context(context)?,
+                quiet_hours: cfg.quiet_hours().with_context(context)?,
+                quiet: cfg.quiet,
+                destination: cfg.destination,
+            };
+
+            let is_telegram = matches!(route.destination, Destination::Telegram { .. });
+            let is_email = matches!(route.destination, Destination::Email { .. });
+            if route.schedule.is_some() && !is_telegram && !is_email {
+                bail!("Digest mode is not supported by {destination_id}");
             }
-            (Some(_), None) => {
-                info!("Finished pushing all timeline.");
-                self.database.drop_cf("timeline")
+            if route.quiet == Quiet::Silent && !is_telegram {
+                bail!("Silent quiet hours are not supported by {destination_id}");
             }
-            _ => {
-                info!("No tweets to push.");
-                Ok(())
+            if let Destination::Slack {
+                webhook_url: None,
+                token,
+                channel,
+            } = &route.destination
+            {
+                if token.is_none() || channel.is_none() {
+                    bail!("Slack destination requires either webhook_url, or token and channel: {destination_id}");
+                }
             }
+            user_map.entry(cfg.from).or_default().push(route);
         }
+        Ok(user_map)
     }
+}
 
-    /// Returns a Twitter username to Telegram channel map.
-    fn user_map(&mut self) -> HashMap<String, String> {
-        self.config
-            .drain(..)
-            .map(|cfg| (cfg.from, cfg.username))
-            .collect()
+impl Route {
+    /// Returns what happens to tweets if `now` is in quiet hours.
+    fn quiet_at(&self, now: DateTime<Utc>) -> Option<Quiet> {
+        let quiet_hours = self.quiet_hours?;
+        quiet_hours
+            .contains(now.with_timezone(&self.timezone).time())
+            .then_some(self.quiet)
     }
 }
 
 impl<'a> Drop for Push<'a> {
     fn drop(&mut self) {
-        let _ = self.tidy_database();
+        if self.dry_run.is_none() {
+            let _ = self.tidy_database();
+        }
+    }
+}
+
+impl Preview {
+    fn print(&self, format: PreviewFormat) -> Result<()> {
+        match format {
+            PreviewFormat::Text => {
+                println!("==> {} ({})", self.destination, self.tweets.join(", "));
+                for message in &self.messages {
+                    println!("{}", serde_json::to_string_pretty(message)?);
+                }
+                println!();
+            }
+            PreviewFormat::Json => println!("{}", serde_json::to_string(self)?),
+        }
+        Ok(())
+    }
+}
+
+/// Returns keys of queued tweets, ordered by username and then tweet ID.
+///
+/// Keys order tweet IDs as text, which isn't their numeric order when lengths differ,
+/// like IDs of tweets imported from an archive which predate Snowflake IDs.
+fn timeline_keys(database: &Database) -> Result<Vec<Box<[u8]>>> {
+    let mut keys = database
+        .iterator_cf("timeline")?
+        .map(|entry| entry.map(|(key, _)| key))
+        .collect::<Result<Vec<_>>>()?;
+    keys.sort_by_cached_key(|key| {
+        let (username, id) = key
+            .iter()
+            .position(|&byte| byte == b':')
+            .map_or((&key[..], &[][..]), |colon| {
+                (&key[..colon], &key[colon + 1..])
+            });
+        (username.to_vec(), id.len(), id.to_vec())
+    });
+    Ok(keys)
+}
+
+/// Renders a single message to be previewed.
+fn to_value(message: impl Serialize) -> Result<Vec<Value>> {
+    Ok(vec![serde_json::to_value(message)?])
+}
+
+/// Returns subject template of an email, falling back to a default one.
+fn email_subject(subject: &Option<String>, digest: bool) -> &str {
+    match (subject, digest) {
+        (Some(subject), _) => subject,
+        (None, true) => "{count} new tweets from {username}",
+        (None, false) => "New tweet from @{username}",
+    }
+}
+
+/// Outcome of sending a tweet to a destination.
+enum Delivery {
+    /// The destination accepted the tweet, with ID of the message it created if known.
+    Sent { message_id: Option<String> },
+    /// The destination rejected the tweet.
+    Rejected { status: StatusCode, body: String },
+}
+
+impl Delivery {
+    /// Reads a response, the message ID is looked up by a JSON pointer into successful response body.
+    async fn from_response(response: Response, message_id: Option<&str>) -> Self {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_else(|_| "".to_string());
+        if !status.is_success() {
+            return Self::Rejected { status, body };
+        }
+
+        let message_id = message_id.and_then(|pointer| {
+            let body: Value = serde_json::from_str(&body).ok()?;
+            match body.pointer(pointer)? {
+                Value::String(message_id) => Some(message_id.clone()),
+                other => Some(other.to_string()),
+            }
+        });
+        Self::Sent { message_id }
     }
 }
 
@@ -162,3 +812,36 @@ fn shutdown_signal() -> Receiver<()> {
     });
     rx
 }
+
+#[cfg(test)]
+mod tests {
+    use super::timeline_keys;
+    use crate::database::Database;
+
+    #[test]
+    fn timeline_keys_in_numeric_order() {
+        let database = Database::memory();
+        for key in [
+            "TwitterDev:1050118621198921728",
+            "TwitterDev:20",
+            "Alice:1001",
+            "TwitterDev:986422891",
+        ] {
+            database.put_cf("timeline", key, "{}").unwrap();
+        }
+        let keys: Vec<_> = timeline_keys(&database)
+            .unwrap()
+            .into_iter()
+            .map(|key| String::from_utf8(key.to_vec()).unwrap())
+            .collect();
+        assert_eq!(
+            vec![
+                "Alice:1001",
+                "TwitterDev:20",
+                "TwitterDev:986422891",
+                "TwitterDev:1050118621198921728",
+            ],
+            keys
+        );
+    }
+}
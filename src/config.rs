@@ -1,13 +1,62 @@
-use serde::Deserialize;
-use std::path::PathBuf;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{de, Deserialize, Deserializer};
+use std::{collections::HashMap, path::PathBuf};
+use url::Url;
+
+use crate::{
+    archive::Retention, email::Encryption, mastodon::Visibility, queue::Limits, retry::RetryPolicy,
+    slack, telegram, thread, twitter,
+};
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
+    /// Storage backend of database.
+    #[serde(default)]
+    pub(crate) storage: StorageKind,
+    #[serde(default = "default_rocksdb_path")]
     pub(crate) rocksdb_path: PathBuf,
+    /// SQLite database file path, required by SQLite storage.
+    pub(crate) sqlite_path: Option<PathBuf>,
+    /// How long to wait for another Pigeon process to release database, for example, "5m".
+    /// By default, commands fail at once if database is in use.
+    pub(crate) lock_timeout: Option<String>,
     pub(crate) twitter_token: Option<String>,
     pub(crate) telegram_token: Option<String>,
+    /// Twitter API base URL, default is "https://api.twitter.com/2/".
+    pub(crate) twitter_api: Option<String>,
+    /// Telegram Bot API base URL, default is "https://api.telegram.org/".
+    pub(crate) telegram_api: Option<String>,
+    /// Slack Web API base URL, default is "https://slack.com/api/".
+    pub(crate) slack_api: Option<String>,
     pub(crate) poll: Option<Vec<PollConfig>>,
     pub(crate) push: Option<Vec<PushConfig>>,
+    /// Whether pushed tweets are moved to archive column family instead of being deleted.
+    #[serde(default)]
+    pub(crate) archive: bool,
+    /// Archived tweets older than this are pruned, for example, "90d".
+    pub(crate) archive_max_age: Option<String>,
+    /// At most this many archived tweets are kept, the oldest are pruned first.
+    pub(crate) archive_max_entries: Option<usize>,
+    /// Mastodon statuses published earlier than this are no longer replied to by threads,
+    /// default is "30d".
+    pub(crate) thread_max_age: Option<String>,
+    /// At most this many tweets of a Twitter user are queued in timeline.
+    pub(crate) max_queue_per_user: Option<usize>,
+    /// Tweets queued earlier than this are dropped, for example, "7d".
+    pub(crate) max_age: Option<String>,
+    /// At most this many bytes of tweets are queued in timeline.
+    pub(crate) max_total_bytes: Option<u64>,
+    /// Which tweets are dropped from a queue over `max_queue_per_user` or `max_total_bytes`.
+    #[serde(default)]
+    pub(crate) drop_policy: DropPolicy,
+    /// At most this many attempts of a request failing with transient errors, default is 3.
+    pub(crate) retry_max_attempts: Option<u32>,
+    /// Delay before retrying a request, doubled on each retry, for example, "1s".
+    pub(crate) retry_base_delay: Option<String>,
+    /// Whether retry delays are randomized, default is true.
+    pub(crate) retry_jitter: Option<bool>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -24,7 +73,459 @@ pub(crate) struct PollConfig {
 pub(crate) struct PushConfig {
     pub(crate) included: bool,
     pub(crate) from: String,
-    pub(crate) username: String,
+    /// Whether tweets are pushed one by one, or gathered into digests.
+    #[serde(default)]
+    pub(crate) mode: Mode,
+    /// Digest interval, for example, "30m", "1h" or "1d".
+    pub(crate) every: Option<String>,
+    /// Digest time of day, for example, "09:00".
+    pub(crate) at: Option<String>,
+    /// Timezone of `at` and `quiet_hours`, for example, "Europe/Berlin", default is UTC.
+    pub(crate) timezone: Option<String>,
+    /// Time range in which tweets are not pushed, for example, "23:00-07:00".
+    pub(crate) quiet_hours: Option<String>,
+    /// What happens to tweets during quiet hours.
+    #[serde(default)]
+    pub(crate) quiet: Quiet,
+    #[serde(flatten)]
+    pub(crate) destination: Destination,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum DropPolicy {
+    /// Drop the earliest queued tweets, so that the latest ones are pushed.
+    #[default]
+    Oldest,
+    /// Drop the latest queued tweets, so that the queue is pushed in full once it's drained.
+    Newest,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum StorageKind {
+    /// RocksDB database in `rocksdb_path`.
+    #[default]
+    Rocksdb,
+    /// SQLite database file in `sqlite_path`, requires `sqlite` cargo feature.
+    Sqlite,
+    /// Database in memory, which is gone when Pigeon exits.
+    Memory,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Mode {
+    /// Push every tweet as soon as possible.
+    #[default]
+    Instant,
+    /// Push all pending tweets at once when a digest is due.
+    Digest,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Quiet {
+    /// Keep tweets queued in database until quiet hours end.
+    #[default]
+    Hold,
+    /// Push tweets without notifications, only supported by Telegram.
+    Silent,
+}
+
+/// A daily time range, which wraps around midnight if it ends before it starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct QuietHours {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+/// When digests of a push route are due.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Schedule {
+    /// A digest is due when the interval has passed since the last one.
+    Every(Duration),
+    /// A digest is due once a day at the time of day.
+    At(NaiveTime),
+}
+
+/// Where a push route delivers tweets to, selected by the `kind` key.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(remote = "Self", tag = "kind", rename_all = "lowercase")]
+pub(crate) enum Destination {
+    Telegram {
+        /// Telegram channel username (for public channels) or chat ID (for private channels).
+        username: String,
+        #[serde(flatten)]
+        options: telegram::Options,
+    },
+    Discord {
+        /// Discord webhook URL, in the form of `https://discord.com/api/webhooks/{id}/{token}`.
+        webhook_url: String,
+    },
+    Matrix {
+        /// Matrix homeserver base URL, for example, "https://matrix.org".
+        homeserver: String,
+        /// Matrix room ID, for example, "!abc:matrix.org".
+        room_id: String,
+        /// Access token of the Matrix user sending messages.
+        access_token: String,
+    },
+    Webhook {
+        /// URL that normalized tweets are posted to as JSON.
+        url: String,
+        /// Extra headers sent with every request.
+        #[serde(default)]
+        headers: HashMap<String, String>,
+        /// Shared secret to sign request body with, the signature is sent in `X-Pigeon-Signature` header.
+        secret: Option<String>,
+    },
+    Slack {
+        /// Slack incoming webhook URL, takes priority over `token` and `channel`.
+        webhook_url: Option<String>,
+        /// Slack bot token to post messages with `chat.postMessage`.
+        token: Option<String>,
+        /// Slack channel ID or name that bot posts to.
+        channel: Option<String>,
+    },
+    Mastodon {
+        /// Mastodon instance base URL, for example, "https://mastodon.social".
+        instance: String,
+        /// Access token of the Mastodon account publishing statuses.
+        access_token: String,
+        /// Visibility of published statuses, default is the account's default visibility.
+        visibility: Option<Visibility>,
+        /// Whether to publish replies in a thread as replies to statuses of the thread.
+        #[serde(default)]
+        threads: bool,
+    },
+    Email {
+        /// SMTP server host.
+        host: String,
+        /// SMTP server port, default port depends on `tls`.
+        port: Option<u16>,
+        #[serde(default)]
+        tls: Encryption,
+        /// SMTP username and password, if the server requires authentication.
+        user: Option<String>,
+        password: Option<String>,
+        /// Email sender mailbox, for example, "Pigeon <pigeon@example.com>".
+        sender: String,
+        recipients: Vec<String>,
+        /// Email subject template.
+        subject: Option<String>,
+    },
+}
+
+impl<'de> Deserialize<'de> for Destination {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // Push configs written before other destinations existed have no `kind`, they are Telegram ones.
+        let mut table = toml::Table::deserialize(deserializer)?;
+        table
+            .entry("kind")
+            .or_insert(toml::Value::String("telegram".into()));
+        Destination::deserialize(toml::Value::Table(table)).map_err(de::Error::custom)
+    }
+}
+
+impl Destination {
+    /// Returns secrets of this destination, including webhook URL tokens.
+    fn secrets(&self) -> Vec<&str> {
+        match self {
+            Destination::Telegram { .. } => vec![],
+            Destination::Discord { webhook_url } => vec![webhook_token(webhook_url)],
+            Destination::Matrix { access_token, .. } => vec![access_token],
+            Destination::Webhook {
+                url,
+                headers,
+                secret,
+            } => headers
+                .values()
+                .map(String::as_str)
+                .chain(secret.as_deref())
+                // URL query may carry credentials, which `id` leaves out too.
+                .chain(url.split_once('?').map(|(_, query)| query))
+                .collect(),
+            Destination::Slack {
+                webhook_url, token, ..
+            } => webhook_url
+                .as_deref()
+                .map(webhook_token)
+                .into_iter()
+                .chain(token.as_deref())
+                .collect(),
+            Destination::Mastodon { access_token, .. } => vec![access_token],
+            Destination::Email { password, .. } => password.as_deref().into_iter().collect(),
+        }
+    }
+
+    /// Returns an identifier of this destination which is safe to log and store.
+    pub(crate) fn id(&self) -> String {
+        match self {
+            Destination::Telegram { username, options } => match options.message_thread_id {
+                // Topics of a forum are told apart, so that each of them is pushed to.
+                Some(thread_id) => format!("telegram:{username}/{thread_id}"),
+                None => format!("telegram:{username}"),
+            },
+            Destination::Discord { webhook_url } => {
+                // Leave out the webhook token which is the last path segment.
+                let webhook_id = webhook_url.rsplit('/').nth(1).unwrap_or_default();
+                format!("discord:{webhook_id}")
+            }
+            Destination::Matrix { room_id, .. } => format!("matrix:{room_id}"),
+            Destination::Webhook { url, .. } => {
+                // Leave out query which may carry credentials.
+                let (url, _) = url.split_once('?').unwrap_or((url, ""));
+                format!("webhook:{url}")
+            }
+            Destination::Slack {
+                webhook_url,
+                channel,
+                ..
+            } => match (webhook_url, channel) {
+                (Some(webhook_url), _) => {
+                    // Leave out the webhook secret which is the last path segment.
+                    let webhook_id = webhook_url.rsplit('/').nth(1).unwrap_or_default();
+                    format!("slack:{webhook_id}")
+                }
+                (None, channel) => format!("slack:{}", channel.as_deref().unwrap_or_default()),
+            },
+            Destination::Mastodon { instance, .. } => format!("mastodon:{instance}"),
+            Destination::Email { recipients, .. } => format!("email:{}", recipients.join(",")),
+        }
+    }
+}
+
+impl PushConfig {
+    /// Returns digest schedule of this push route, or `None` if tweets are pushed one by one.
+    pub(crate) fn schedule(&self) -> Result<Option<Schedule>> {
+        if self.mode == Mode::Instant {
+            return Ok(None);
+        }
+        let schedule = match (&self.every, &self.at) {
+            (Some(every), None) => Schedule::Every(parse_interval(every)?),
+            (None, Some(at)) => Schedule::At(
+                NaiveTime::parse_from_str(at, "%H:%M")
+                    .with_context(|| format!("Invalid digest time of day: {at}"))?,
+            ),
+            _ => bail!("Digest mode requires either `every` or `at`"),
+        };
+        Ok(Some(schedule))
+    }
+
+    pub(crate) fn timezone(&self) -> Result<Tz> {
+        match &self.timezone {
+            Some(timezone) => timezone
+                .parse()
+                .map_err(|e| anyhow!("Invalid timezone: {timezone}, {e}")),
+            None => Ok(Tz::UTC),
+        }
+    }
+
+    pub(crate) fn quiet_hours(&self) -> Result<Option<QuietHours>> {
+        let Some(quiet_hours) = &self.quiet_hours else {
+            return Ok(None);
+        };
+        let parse = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .with_context(|| format!("Invalid quiet hours: {quiet_hours}"))
+        };
+        let Some((start, end)) = quiet_hours.split_once('-') else {
+            bail!("Invalid quiet hours, expected a range like \"23:00-07:00\": {quiet_hours}");
+        };
+        Ok(Some(QuietHours {
+            start: parse(start)?,
+            end: parse(end)?,
+        }))
+    }
+}
+
+impl QuietHours {
+    pub(crate) fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+impl Schedule {
+    /// Checks whether a digest is due at `now`, given when the last digest was sent.
+    /// The time of day is in the timezone of `now`, and the first digest of a push route is due at once.
+    pub(crate) fn is_due(&self, last_sent: Option<DateTime<Utc>>, now: DateTime<Tz>) -> bool {
+        let Some(last_sent) = last_sent else {
+            return true;
+        };
+        match self {
+            Schedule::Every(interval) => last_sent + *interval <= now,
+            Schedule::At(time) => {
+                // The latest time of day reached up to now.
+                let timezone = now.timezone();
+                let local = now.date_naive().and_time(*time);
+                let mut latest = timezone
+                    .from_local_datetime(&local)
+                    .earliest()
+                    // The time of day is skipped by a daylight saving transition.
+                    .unwrap_or_else(|| timezone.from_utc_datetime(&local));
+                if latest > now {
+                    latest -= Duration::days(1);
+                }
+                last_sent < latest
+            }
+        }
+    }
+}
+
+/// Parses an interval like "90s", "30m", "1h" or "1d".
+fn parse_interval(interval: &str) -> Result<Duration> {
+    let unit_index = interval
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(interval.len());
+    let (amount, unit) = interval.split_at(unit_index);
+    let amount: i64 = amount
+        .parse()
+        .with_context(|| format!("Invalid interval: {interval}"))?;
+    let duration = match unit {
+        "s" => Duration::seconds(amount),
+        "m" => Duration::minutes(amount),
+        "h" => Duration::hours(amount),
+        "d" => Duration::days(amount),
+        _ => bail!("Invalid interval unit, expected s, m, h or d: {interval}"),
+    };
+    if duration <= Duration::zero() {
+        bail!("Interval must be positive: {interval}");
+    }
+    Ok(duration)
+}
+
+impl Config {
+    pub(crate) fn twitter_api(&self) -> Result<Url> {
+        base_url(
+            self.twitter_api
+                .as_deref()
+                .unwrap_or(twitter::API_ENDPOINT_BASE),
+        )
+        .with_context(|| "Invalid Twitter API base URL")
+    }
+
+    pub(crate) fn telegram_api(&self) -> Result<Url> {
+        base_url(self.telegram_api.as_deref().unwrap_or(telegram::API_BASE))
+            .with_context(|| "Invalid Telegram Bot API base URL")
+    }
+
+    pub(crate) fn slack_api(&self) -> Result<Url> {
+        base_url(self.slack_api.as_deref().unwrap_or(slack::API_BASE))
+            .with_context(|| "Invalid Slack Web API base URL")
+    }
+
+    /// Returns retention policy of archive, or `None` if archive is off.
+    pub(crate) fn retention(&self) -> Result<Option<Retention>> {
+        if !self.archive {
+            return Ok(None);
+        }
+        let max_age = self
+            .archive_max_age
+            .as_deref()
+            .map(parse_interval)
+            .transpose()
+            .with_context(|| "Invalid archive_max_age")?;
+        Ok(Some(Retention {
+            max_age,
+            max_entries: self.archive_max_entries,
+        }))
+    }
+
+    /// Returns how long Mastodon statuses are kept for replies to be threaded to.
+    pub(crate) fn thread_max_age(&self) -> Result<Duration> {
+        self.thread_max_age
+            .as_deref()
+            .map(parse_interval)
+            .transpose()
+            .with_context(|| "Invalid thread_max_age")
+            .map(|max_age| max_age.unwrap_or(thread::DEFAULT_MAX_AGE))
+    }
+
+    /// Returns caps of tweets queued in timeline.
+    pub(crate) fn limits(&self) -> Result<Limits> {
+        let max_age = self
+            .max_age
+            .as_deref()
+            .map(parse_interval)
+            .transpose()
+            .with_context(|| "Invalid max_age")?;
+        Ok(Limits {
+            max_queue_per_user: self.max_queue_per_user,
+            max_age,
+            max_total_bytes: self.max_total_bytes,
+            drop_policy: self.drop_policy,
+        })
+    }
+
+    /// Returns how long to wait for lock of database, or `None` to fail at once.
+    pub(crate) fn lock_timeout(&self) -> Result<Option<std::time::Duration>> {
+        let Some(lock_timeout) = self.lock_timeout.as_deref() else {
+            return Ok(None);
+        };
+        let lock_timeout = parse_interval(lock_timeout)
+            .and_then(|duration| Ok(duration.to_std()?))
+            .with_context(|| "Invalid lock_timeout")?;
+        Ok(Some(lock_timeout))
+    }
+
+    /// Returns how requests failing with transient errors are retried.
+    pub(crate) fn retry_policy(&self) -> Result<RetryPolicy> {
+        let default = RetryPolicy::default();
+        let max_attempts = self.retry_max_attempts.unwrap_or(default.max_attempts);
+        if max_attempts == 0 {
+            bail!("retry_max_attempts must be at least 1");
+        }
+        let base_delay = match self.retry_base_delay.as_deref() {
+            Some(base_delay) => parse_interval(base_delay)
+                .and_then(|duration| Ok(duration.to_std()?))
+                .with_context(|| "Invalid retry_base_delay")?,
+            None => default.base_delay,
+        };
+        Ok(RetryPolicy {
+            max_attempts,
+            base_delay,
+            jitter: self.retry_jitter.unwrap_or(default.jitter),
+        })
+    }
+
+    /// Returns tokens and other secrets in config, which must not leak out of it.
+    pub(crate) fn secrets(&self) -> Vec<String> {
+        let mut secrets: Vec<String> = [&self.twitter_token, &self.telegram_token]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+        for cfg in self.push.iter().flatten() {
+            secrets.extend(cfg.destination.secrets().into_iter().map(String::from));
+        }
+        secrets
+    }
+}
+
+fn default_rocksdb_path() -> PathBuf {
+    "rocksdb".into()
+}
+
+/// Returns the token of a Discord or Slack webhook URL, which is its last path segment.
+fn webhook_token(webhook_url: &str) -> &str {
+    webhook_url.rsplit('/').next().unwrap_or_default()
+}
+
+/// Parses a base URL, a trailing slash is appended so that api paths are joined under it.
+fn base_url(url: &str) -> Result<Url> {
+    let mut url = Url::parse(url)?;
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    Ok(url)
 }
 
 impl PollConfig {
@@ -35,7 +536,11 @@ impl PollConfig {
 
 #[cfg(test)]
 mod tests {
-    use super::Config;
+    use chrono::{DateTime, Duration, NaiveTime};
+    use chrono_tz::Tz;
+
+    use super::{Config, Destination, Quiet, Schedule};
+    use crate::{archive::Retention, retry::RetryPolicy, telegram::LinkPreview};
 
     #[test]
     fn decode() {
@@ -60,4 +565,244 @@ mod tests {
         let decoded = toml::from_str::<Config>(toml_str);
         assert!(decoded.is_ok());
     }
+
+    #[test]
+    fn api_base_urls() {
+        let toml_str = r#"
+        rocksdb_path = "rocksdb"
+        twitter_api = "http://localhost:8080/2"
+        "#;
+        let config = toml::from_str::<Config>(toml_str).unwrap();
+        assert_eq!(
+            "http://localhost:8080/2/users/by",
+            config
+                .twitter_api()
+                .unwrap()
+                .join("users/by")
+                .unwrap()
+                .as_str()
+        );
+        assert_eq!(
+            "https://api.telegram.org/",
+            config.telegram_api().unwrap().as_str()
+        );
+    }
+
+    #[test]
+    fn retention() {
+        let config = toml::from_str::<Config>(r#"rocksdb_path = "rocksdb""#).unwrap();
+        assert_eq!(None, config.retention().unwrap());
+
+        let toml_str = r#"
+        rocksdb_path = "rocksdb"
+        archive = true
+        archive_max_age = "90d"
+        "#;
+        let config = toml::from_str::<Config>(toml_str).unwrap();
+        assert_eq!(
+            Some(Retention {
+                max_age: Some(Duration::days(90)),
+                max_entries: None,
+            }),
+            config.retention().unwrap()
+        );
+        assert_eq!(Duration::days(30), config.thread_max_age().unwrap());
+        let config = toml::from_str::<Config>(r#"thread_max_age = "7d""#).unwrap();
+        assert_eq!(Duration::days(7), config.thread_max_age().unwrap());
+    }
+
+    #[test]
+    fn lock_timeout() {
+        let config = toml::from_str::<Config>(r#"lock_timeout = "5m""#).unwrap();
+        assert_eq!(
+            Some(std::time::Duration::from_secs(300)),
+            config.lock_timeout().unwrap()
+        );
+        let config = toml::from_str::<Config>(r#"lock_timeout = "5x""#).unwrap();
+        assert!(config.lock_timeout().is_err());
+    }
+
+    #[test]
+    fn retry_policy() {
+        let config = toml::from_str::<Config>("").unwrap();
+        assert_eq!(RetryPolicy::default(), config.retry_policy().unwrap());
+        let toml_str = r#"
+        retry_max_attempts = 5
+        retry_base_delay = "2s"
+        retry_jitter = false
+        "#;
+        let config = toml::from_str::<Config>(toml_str).unwrap();
+        assert_eq!(
+            RetryPolicy {
+                max_attempts: 5,
+                base_delay: std::time::Duration::from_secs(2),
+                jitter: false,
+            },
+            config.retry_policy().unwrap()
+        );
+        let config = toml::from_str::<Config>("retry_max_attempts = 0").unwrap();
+        assert!(config.retry_policy().is_err());
+    }
+
+    #[test]
+    fn decode_destinations() {
+        let toml_str = r#"
+        rocksdb_path = "rocksdb"
+
+        [[push]]
+        included = true
+        from = "TwitterDev"
+        username = "some_bot"
+
+        [[push]]
+        included = true
+        from = "TwitterDev"
+        kind = "discord"
+        webhook_url = "https://discord.com/api/webhooks/123/secret"
+
+        [[push]]
+        included = true
+        from = "TwitterDev"
+        username = "@some_group"
+        link_preview = "disabled"
+        message_thread_id = 42
+        "#;
+        let push = toml::from_str::<Config>(toml_str).unwrap().push.unwrap();
+        assert!(matches!(
+            &push[0].destination,
+            Destination::Telegram { username, .. } if username == "some_bot"
+        ));
+        assert_eq!("discord:123", push[1].destination.id());
+        assert!(matches!(
+            &push[2].destination,
+            Destination::Telegram { options, .. }
+                if options.link_preview == Some(LinkPreview::Disabled)
+                    && options.message_thread_id == Some(42)
+        ));
+    }
+
+    #[test]
+    fn destination_id() {
+        // A topic per account in one forum group.
+        let toml_str = r#"
+        [[push]]
+        included = true
+        from = "TwitterDev"
+        username = "@some_group"
+        message_thread_id = 42
+
+        [[push]]
+        included = true
+        from = "jack"
+        username = "@some_group"
+        message_thread_id = 43
+
+        [[push]]
+        included = true
+        from = "jack"
+        username = "@some_channel"
+        "#;
+        let push = toml::from_str::<Config>(toml_str).unwrap().push.unwrap();
+        assert_eq!("telegram:@some_group/42", push[0].destination.id());
+        assert_eq!("telegram:@some_group/43", push[1].destination.id());
+        assert_eq!("telegram:@some_channel", push[2].destination.id());
+    }
+
+    #[test]
+    fn schedule() {
+        let toml_str = r#"
+        rocksdb_path = "rocksdb"
+
+        [[push]]
+        included = true
+        from = "TwitterDev"
+        username = "some_bot"
+        mode = "digest"
+        every = "1h"
+
+        [[push]]
+        included = true
+        from = "TwitterDev"
+        username = "some_bot"
+        mode = "digest"
+        at = "09:00"
+
+        [[push]]
+        included = true
+        from = "TwitterDev"
+        username = "some_bot"
+        every = "1h"
+        "#;
+        let push = toml::from_str::<Config>(toml_str).unwrap().push.unwrap();
+        let time = |s| DateTime::parse_from_rfc3339(s).unwrap().to_utc();
+        let now = |s| time(s).with_timezone(&Tz::UTC);
+
+        let every = push[0].schedule().unwrap().unwrap();
+        assert_eq!(Schedule::Every(Duration::hours(1)), every);
+        assert!(every.is_due(None, now("2024-01-01T10:00:00Z")));
+        assert!(!every.is_due(
+            Some(time("2024-01-01T09:30:00Z")),
+            now("2024-01-01T10:00:00Z")
+        ));
+        assert!(every.is_due(
+            Some(time("2024-01-01T09:00:00Z")),
+            now("2024-01-01T10:00:00Z")
+        ));
+
+        let at = push[1].schedule().unwrap().unwrap();
+        assert!(at.is_due(
+            Some(time("2024-01-01T08:59:00Z")),
+            now("2024-01-01T09:00:00Z")
+        ));
+        assert!(!at.is_due(
+            Some(time("2024-01-01T09:01:00Z")),
+            now("2024-01-01T23:00:00Z")
+        ));
+        assert!(at.is_due(
+            Some(time("2024-01-01T09:01:00Z")),
+            now("2024-01-02T09:01:00Z")
+        ));
+
+        assert_eq!(None, push[2].schedule().unwrap());
+    }
+
+    #[test]
+    fn quiet_hours() {
+        let toml_str = r#"
+        rocksdb_path = "rocksdb"
+
+        [[push]]
+        included = true
+        from = "TwitterDev"
+        username = "some_bot"
+        mode = "digest"
+        at = "09:00"
+        timezone = "Europe/Berlin"
+        quiet_hours = "23:00-07:00"
+        quiet = "silent"
+        "#;
+        let push = toml::from_str::<Config>(toml_str).unwrap().push.unwrap();
+        assert_eq!(Quiet::Silent, push[0].quiet);
+        let timezone = push[0].timezone().unwrap();
+        assert_eq!(Tz::Europe__Berlin, timezone);
+
+        let quiet_hours = push[0].quiet_hours().unwrap().unwrap();
+        let time = |s| NaiveTime::parse_from_str(s, "%H:%M").unwrap();
+        assert!(quiet_hours.contains(time("23:30")));
+        assert!(quiet_hours.contains(time("06:59")));
+        assert!(!quiet_hours.contains(time("07:00")));
+        assert!(!quiet_hours.contains(time("12:00")));
+
+        // 09:00 in Berlin is 08:00 UTC in winter.
+        let at = push[0].schedule().unwrap().unwrap();
+        let utc = |s| DateTime::parse_from_rfc3339(s).unwrap().to_utc();
+        assert!(at.is_due(
+            Some(utc("2024-01-01T07:59:00Z")),
+            utc("2024-01-01T08:00:00Z").with_timezone(&timezone)
+        ));
+        assert!(!at.is_due(
+            Some(utc("2024-01-01T08:01:00Z")),
+            utc("2024-01-01T22:00:00Z").with_timezone(&timezone)
+        ));
+    }
 }
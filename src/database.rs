@@ -1,77 +1,371 @@
-use anyhow::{bail, Context, Result};
-use rocksdb::{ColumnFamilyDescriptor, DBIterator, IteratorMode, Options, DB};
-use std::path::Path;
+use anyhow::{anyhow, bail, Result};
+use std::{iter, path::Path, time::Duration};
 
-#[derive(Debug)]
-pub(crate) struct Database(DB);
+use crate::config::{Config, StorageKind};
 
-impl Database {
-    pub(crate) fn open<P: AsRef<Path>>(path: P) -> Self {
-        let cfds: Vec<ColumnFamilyDescriptor> = vec!["timeline", "state"]
-            .iter()
-            .map(|&cf| ColumnFamilyDescriptor::new(cf, Options::default()))
-            .collect();
+#[cfg(any(feature = "rocksdb", feature = "sqlite"))]
+mod lock;
+mod memory;
+mod migration;
+#[cfg(feature = "rocksdb")]
+mod rocksdb;
+#[cfg(feature = "sqlite")]
+mod sqlite;
 
-        let mut db_opts = Options::default();
-        db_opts.create_if_missing(true);
-        db_opts.create_missing_column_families(true);
+pub(crate) use migration::{SCHEMA_VERSION, SCHEMA_VERSION_KEY};
 
-        let db = DB::open_cf_descriptors(&db_opts, path, cfds).expect("could not open rocksdb");
-        Self(db)
-    }
+/// Column families of database.
+pub(crate) const COLUMN_FAMILIES: [&str; 8] = [
+    "timeline",
+    "state",
+    "delivery",
+    "thread",
+    "digest",
+    "archive",
+    "meta",
+    "dead_letter",
+];
 
-    pub(crate) fn put_cf<K, V>(&self, cf: &str, key: K, value: V) -> Result<()>
+/// Entries of a column family in key order.
+pub(crate) type Entries<'a> = Box<dyn Iterator<Item = Result<(Box<[u8]>, Box<[u8]>)>> + 'a>;
+
+/// Entries read at once by storages which can't keep a read open while iterating.
+const PAGE_SIZE: usize = 256;
+
+/// Key-value operations that commands need from a storage backend.
+///
+/// Data is kept in column families of `COLUMN_FAMILIES`, and keys of a column
+/// family are ordered bytewise like RocksDB does.
+pub(crate) trait Storage: Send {
+    fn put(&self, cf: &str, key: &[u8], value: &[u8]) -> Result<()>;
+
+    fn get(&self, cf: &str, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    fn delete(&self, cf: &str, key: &[u8]) -> Result<()>;
+
+    /// Deletes all keys of a column family.
+    fn clear(&mut self, cf: &str) -> Result<()>;
+
+    /// Iterates entries in key order, starting from `from` inclusive.
+    fn iter_from(&self, cf: &str, from: &[u8]) -> Result<Entries<'_>>;
+
+    /// Applies all writes of a batch, or none of them.
+    fn write(&self, batch: WriteBatch) -> Result<()>;
+}
+
+/// Writes applied atomically by `Database::write`, in the order they're added.
+#[derive(Debug, Default)]
+pub(crate) struct WriteBatch(Vec<Write>);
+
+#[derive(Debug)]
+enum Write {
+    Put {
+        cf: String,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Delete {
+        cf: String,
+        key: Vec<u8>,
+    },
+    DeleteRange {
+        cf: String,
+        from: Vec<u8>,
+        to: Vec<u8>,
+    },
+}
+
+impl WriteBatch {
+    pub(crate) fn put_cf<K, V>(&mut self, cf: &str, key: K, value: V)
     where
         K: AsRef<[u8]>,
         V: AsRef<[u8]>,
     {
-        match self.0.cf_handle(cf) {
-            Some(cf_handle) => Ok(self.0.put_cf(cf_handle, key, value)?),
-            None => bail!("no such column family: {cf}"),
+        self.0.push(Write::Put {
+            cf: cf.into(),
+            key: key.as_ref().to_vec(),
+            value: value.as_ref().to_vec(),
+        });
+    }
+
+    pub(crate) fn delete_cf<K: AsRef<[u8]>>(&mut self, cf: &str, key: K) {
+        self.0.push(Write::Delete {
+            cf: cf.into(),
+            key: key.as_ref().to_vec(),
+        });
+    }
+
+    /// Deletes keys from `from` inclusive to `to` exclusive (`["from", "to")`).
+    pub(crate) fn delete_range_cf<K: AsRef<[u8]>>(&mut self, cf: &str, from: K, to: K) {
+        self.0.push(Write::DeleteRange {
+            cf: cf.into(),
+            from: from.as_ref().to_vec(),
+            to: to.as_ref().to_vec(),
+        });
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns an error if any write is to an unknown column family.
+    fn check(&self) -> Result<()> {
+        self.0.iter().try_for_each(|write| match write {
+            Write::Put { cf, .. } | Write::Delete { cf, .. } | Write::DeleteRange { cf, .. } => {
+                check_cf(cf)
+            }
+        })
+    }
+}
+
+/// Database of Pigeon on a storage backend selected in config.
+pub(crate) struct Database {
+    storage: Box<dyn Storage>,
+    /// Lock held while database is open for writing, `None` in memory or read-only.
+    #[cfg(any(feature = "rocksdb", feature = "sqlite"))]
+    _lock: Option<lock::Lock>,
+}
+
+/// How a database is opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Access {
+    /// Locked for writing, waiting at most the timeout if another process holds the lock.
+    ReadWrite(Option<Duration>),
+    /// Read-only without lock, so that it's readable while another process writes it.
+    ReadOnly,
+}
+
+impl Database {
+    fn new(storage: impl Storage + 'static) -> Self {
+        Self {
+            storage: Box::new(storage),
+            #[cfg(any(feature = "rocksdb", feature = "sqlite"))]
+            _lock: None,
         }
     }
 
-    pub(crate) fn get_cf<K: AsRef<[u8]>>(&self, cf: &str, key: K) -> Result<Option<Vec<u8>>> {
-        match self.0.cf_handle(cf) {
-            Some(cf_handle) => self
-                .0
-                .get_cf(cf_handle, key)
-                .with_context(|| "could not get value from column family"),
-            None => bail!("no such column family: {cf}"),
+    /// Holds a lock on database files as long as database is open.
+    #[cfg(any(feature = "rocksdb", feature = "sqlite"))]
+    fn with_lock(self, lock: lock::Lock) -> Self {
+        Self {
+            _lock: Some(lock),
+            ..self
+        }
+    }
+
+    /// Opens storage backend selected in config.
+    pub(crate) fn from_config(config: &Config, access: Access) -> Result<Self> {
+        match config.storage {
+            StorageKind::Rocksdb => Self::rocksdb(&config.rocksdb_path, access),
+            StorageKind::Sqlite => {
+                let path = config
+                    .sqlite_path
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("sqlite_path is required by SQLite storage"))?;
+                Self::sqlite(path, access)
+            }
+            StorageKind::Memory => Ok(Self::memory()),
         }
     }
 
-    pub(crate) fn iterator_cf(&self, cf: &str) -> Option<DBIterator> {
-        self.0
-            .cf_handle(cf)
-            .map(|cf_handle| self.0.iterator_cf(cf_handle, IteratorMode::Start))
+    /// Opens a RocksDB database, which is created if it's missing, unless it's read-only.
+    #[cfg(feature = "rocksdb")]
+    pub(crate) fn rocksdb(path: &Path, access: Access) -> Result<Self> {
+        Ok(match access {
+            Access::ReadWrite(timeout) => {
+                let lock = lock::Lock::acquire(path, timeout)?;
+                Self::new(rocksdb::RocksDb::open(path)?).with_lock(lock)
+            }
+            Access::ReadOnly => Self::new(rocksdb::RocksDb::open_read_only(path)?),
+        })
     }
 
-    /// Performs an `from` inclusive but `to` exclusive range (`["from", "to")`) deletion.
-    pub(crate) fn delete_range_cf<K>(&self, cf: &str, from: K, to: K) -> Result<()>
+    #[cfg(not(feature = "rocksdb"))]
+    pub(crate) fn rocksdb(_path: &Path, _access: Access) -> Result<Self> {
+        bail!("Pigeon is built without RocksDB storage, please enable `rocksdb` cargo feature")
+    }
+
+    /// Opens a SQLite database file, which is created if it's missing, unless it's read-only.
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn sqlite(path: &Path, access: Access) -> Result<Self> {
+        Ok(match access {
+            Access::ReadWrite(timeout) => {
+                let lock = lock::Lock::acquire(path, timeout)?;
+                Self::new(sqlite::Sqlite::open(path)?).with_lock(lock)
+            }
+            Access::ReadOnly => Self::new(sqlite::Sqlite::open_read_only(path)?),
+        })
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    pub(crate) fn sqlite(_path: &Path, _access: Access) -> Result<Self> {
+        bail!("Pigeon is built without SQLite storage, please enable `sqlite` cargo feature")
+    }
+
+    /// Creates an empty database in memory, which is gone when it's dropped.
+    pub(crate) fn memory() -> Self {
+        Self::new(memory::Memory::default())
+    }
+
+    pub(crate) fn put_cf<K, V>(&self, cf: &str, key: K, value: V) -> Result<()>
     where
         K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
     {
-        match self.0.cf_handle(cf) {
-            Some(cf_handle) => Ok(self.0.delete_range_cf(cf_handle, from, to)?),
-            None => bail!("no such column family: {cf}"),
+        self.storage.put(cf, key.as_ref(), value.as_ref())
+    }
+
+    pub(crate) fn get_cf<K: AsRef<[u8]>>(&self, cf: &str, key: K) -> Result<Option<Vec<u8>>> {
+        self.storage.get(cf, key.as_ref())
+    }
+
+    pub(crate) fn iterator_cf(&self, cf: &str) -> Result<Entries<'_>> {
+        self.storage.iter_from(cf, &[])
+    }
+
+    /// Iterates a column family forward, starting from `from` inclusive.
+    pub(crate) fn iterator_cf_from<K: AsRef<[u8]>>(
+        &self,
+        cf: &str,
+        from: K,
+    ) -> Result<Entries<'_>> {
+        self.storage.iter_from(cf, from.as_ref())
+    }
+
+    pub(crate) fn delete_cf<K: AsRef<[u8]>>(&self, cf: &str, key: K) -> Result<()> {
+        self.storage.delete(cf, key.as_ref())
+    }
+
+    /// Applies all writes of a batch atomically, a crash leaves either all or none of them.
+    pub(crate) fn write(&self, batch: WriteBatch) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
         }
+        self.storage.write(batch)
     }
 
-    pub(crate) fn drop_cf(&mut self, cf: &str) -> Result<()> {
-        Ok(self.0.drop_cf(cf)?)
+    /// Deletes all keys of a column family.
+    pub(crate) fn clear_cf(&mut self, cf: &str) -> Result<()> {
+        self.storage.clear(cf)
+    }
+}
+
+/// Returns an error if a column family is not one of `COLUMN_FAMILIES`.
+fn check_cf(cf: &str) -> Result<()> {
+    if !COLUMN_FAMILIES.contains(&cf) {
+        bail!("no such column family: {cf}");
     }
+    Ok(())
+}
+
+/// Iterates entries page by page from `from` inclusive, for storages which lock their data
+/// while reading, so that it's writable between pages.
+///
+/// `read_page` returns at most `PAGE_SIZE` entries in key order from a key inclusive.
+fn paged<'a, F>(from: &[u8], read_page: F) -> Entries<'a>
+where
+    F: Fn(&[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> + 'a,
+{
+    let mut from = Some(from.to_vec());
+    let mut page = Vec::<(Vec<u8>, Vec<u8>)>::new().into_iter();
+    Box::new(iter::from_fn(move || loop {
+        if let Some((key, value)) = page.next() {
+            return Some(Ok((key.into(), value.into())));
+        }
+        let entries = match read_page(&from.take()?) {
+            Ok(entries) => entries,
+            Err(err) => return Some(Err(err)),
+        };
+        if entries.len() == PAGE_SIZE {
+            // The next page starts from the smallest key after the last one of this page.
+            from = entries.last().map(|(key, _)| [&key[..], &[0]].concat());
+        }
+        page = entries.into_iter();
+    }))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Database;
-    use rocksdb::{Options, DB};
+    use super::{Database, WriteBatch, PAGE_SIZE};
+
+    /// Checks a storage backend against the behavior of RocksDB.
+    pub(crate) fn check_storage(database: Database) {
+        let mut database = database;
+        assert!(database.put_cf("nowhere", "key", "value").is_err());
+        for key in ["a/2", "a/1", "b", "a/3", "a"] {
+            database.put_cf("delivery", key, key).unwrap();
+        }
+        database.put_cf("state", "a/1", "state").unwrap();
+        assert_eq!(
+            Some(b"a/1".to_vec()),
+            database.get_cf("delivery", "a/1").unwrap()
+        );
+        assert_eq!(None, database.get_cf("delivery", "c").unwrap());
+
+        let keys = |database: &Database, from: &str| -> Vec<String> {
+            database
+                .iterator_cf_from("delivery", from)
+                .unwrap()
+                .map(|entry| String::from_utf8(entry.unwrap().0.to_vec()).unwrap())
+                .collect()
+        };
+        assert_eq!(vec!["a", "a/1", "a/2", "a/3", "b"], keys(&database, ""));
+        assert_eq!(vec!["a/2", "a/3", "b"], keys(&database, "a/2"));
+
+        let mut batch = WriteBatch::default();
+        batch.delete_range_cf("delivery", "a/", "a0");
+        database.write(batch).unwrap();
+        database.delete_cf("delivery", "b").unwrap();
+        assert_eq!(vec!["a"], keys(&database, ""));
+
+        // A batch is written as a whole, or not at all.
+        let mut batch = WriteBatch::default();
+        batch.put_cf("delivery", "c", "c");
+        batch.put_cf("nowhere", "c", "c");
+        assert!(database.write(batch).is_err());
+        assert_eq!(None, database.get_cf("delivery", "c").unwrap());
+        let mut batch = WriteBatch::default();
+        batch.put_cf("delivery", "c", "c");
+        batch.put_cf("delivery", "d", "d");
+        batch.delete_cf("delivery", "a");
+        batch.delete_cf("delivery", "d");
+        batch.put_cf("delivery", "c/1", "c/1");
+        batch.put_cf("delivery", "c/2", "c/2");
+        batch.delete_range_cf("delivery", "c/", "c0");
+        assert_eq!(7, batch.len());
+        database.write(batch).unwrap();
+        assert_eq!(vec!["c"], keys(&database, ""));
+
+        database.clear_cf("delivery").unwrap();
+        assert!(keys(&database, "").is_empty());
+
+        // Entries are iterated past a page, and are writable while iterating.
+        for n in 0..PAGE_SIZE * 2 + 1 {
+            database.put_cf("timeline", format!("{n:04}"), "").unwrap();
+        }
+        let mut count = 0;
+        for entry in database.iterator_cf_from("timeline", "0001").unwrap() {
+            let (key, _) = entry.unwrap();
+            database.delete_cf("timeline", key).unwrap();
+            count += 1;
+        }
+        assert_eq!(PAGE_SIZE * 2, count);
+        assert_eq!(1, database.iterator_cf("timeline").unwrap().count());
+        database.put_cf("delivery", "c", "c").unwrap();
+        assert_eq!(vec!["c"], keys(&database, ""));
+        // Other column families are left as they are.
+        assert_eq!(
+            Some(b"state".to_vec()),
+            database.get_cf("state", "a/1").unwrap()
+        );
+    }
 
     #[test]
-    fn open() {
-        let db = Database::open("test");
-        drop(db);
-        DB::destroy(&Options::default(), "test").unwrap();
+    fn memory() {
+        check_storage(Database::memory());
     }
 }
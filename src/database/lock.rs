@@ -0,0 +1,94 @@
+use anyhow::{bail, Context, Result};
+use std::{
+    ffi::OsString,
+    fs::{File, OpenOptions, TryLockError},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::info;
+
+/// How often a held lock is tried again while waiting for it.
+const RETRY_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Exclusive lock on a database, so that only one Pigeon process writes it at a time.
+///
+/// The lock is taken on `<database path>.lock`, and released when it's dropped,
+/// or by the operating system when the process exits.
+#[derive(Debug)]
+pub(crate) struct Lock {
+    _file: File,
+}
+
+impl Lock {
+    /// Locks database at `path`, waiting at most `timeout` if another process holds it.
+    pub(crate) fn acquire(path: &Path, timeout: Option<Duration>) -> Result<Self> {
+        let lock_path = lock_path(path);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("Could not open lock file: {}", lock_path.display()))?;
+
+        let started_at = Instant::now();
+        let mut logged = false;
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(Self { _file: file }),
+                Err(TryLockError::WouldBlock) => {}
+                Err(TryLockError::Error(err)) => {
+                    return Err(err).with_context(|| {
+                        format!("Could not lock database: {}", lock_path.display())
+                    })
+                }
+            }
+            match timeout {
+                Some(timeout) if started_at.elapsed() < timeout => {
+                    if !logged {
+                        info!(
+                            "Waiting for another pigeon process to release {}",
+                            path.display()
+                        );
+                        logged = true;
+                    }
+                    thread::sleep(RETRY_INTERVAL);
+                }
+                _ => bail!(in_use(path)),
+            }
+        }
+    }
+}
+
+/// Error message when database is held by another process.
+pub(crate) fn in_use(path: &Path) -> String {
+    format!("another pigeon process is using {}", path.display())
+}
+
+fn lock_path(path: &Path) -> PathBuf {
+    let mut lock_path = OsString::from(path.as_os_str());
+    lock_path.push(".lock");
+    lock_path.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{lock_path, Lock};
+
+    #[test]
+    fn acquire() {
+        let path = std::env::temp_dir().join(format!("pigeon-lock-{}", std::process::id()));
+        let lock = Lock::acquire(&path, None).unwrap();
+        let err = Lock::acquire(&path, Some(Duration::from_millis(300))).unwrap_err();
+        assert_eq!(
+            format!("another pigeon process is using {}", path.display()),
+            err.to_string()
+        );
+
+        drop(lock);
+        assert!(Lock::acquire(&path, None).is_ok());
+        std::fs::remove_file(lock_path(&path)).unwrap();
+    }
+}
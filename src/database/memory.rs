@@ -0,0 +1,82 @@
+use anyhow::Result;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use super::{check_cf, paged, Entries, Storage, Write, WriteBatch, PAGE_SIZE};
+
+/// Sorted entries of a column family.
+type ColumnFamily = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// Storage in memory, mostly for tests.
+#[derive(Debug, Default)]
+pub(crate) struct Memory(Mutex<HashMap<String, ColumnFamily>>);
+
+impl Memory {
+    fn with_cf<T>(&self, cf: &str, f: impl FnOnce(&mut ColumnFamily) -> T) -> Result<T> {
+        check_cf(cf)?;
+        // Unwrap it directly since the lock is never held across a panic.
+        let mut column_families = self.0.lock().unwrap();
+        Ok(f(column_families.entry(cf.into()).or_default()))
+    }
+}
+
+impl Storage for Memory {
+    fn put(&self, cf: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        self.with_cf(cf, |entries| {
+            entries.insert(key.to_vec(), value.to_vec());
+        })
+    }
+
+    fn get(&self, cf: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.with_cf(cf, |entries| entries.get(key).cloned())
+    }
+
+    fn delete(&self, cf: &str, key: &[u8]) -> Result<()> {
+        self.with_cf(cf, |entries| {
+            entries.remove(key);
+        })
+    }
+
+    fn clear(&mut self, cf: &str) -> Result<()> {
+        self.with_cf(cf, |entries| entries.clear())
+    }
+
+    fn iter_from(&self, cf: &str, from: &[u8]) -> Result<Entries<'_>> {
+        check_cf(cf)?;
+        let cf = cf.to_string();
+        // Entries are copied a page at a time, so that the lock is not held while iterating.
+        Ok(paged(from, move |from| {
+            self.with_cf(&cf, |entries| {
+                entries
+                    .range(from.to_vec()..)
+                    .take(PAGE_SIZE)
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect()
+            })
+        }))
+    }
+
+    fn write(&self, batch: WriteBatch) -> Result<()> {
+        batch.check()?;
+        let mut column_families = self.0.lock().unwrap();
+        for write in batch.0 {
+            match write {
+                Write::Put { cf, key, value } => {
+                    column_families.entry(cf).or_default().insert(key, value);
+                }
+                Write::Delete { cf, key } => {
+                    column_families.entry(cf).or_default().remove(&key);
+                }
+                Write::DeleteRange { cf, from, to } => {
+                    column_families
+                        .entry(cf)
+                        .or_default()
+                        .retain(|key, _| *key < from || *key >= to);
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+use anyhow::{bail, Context, Result};
+use std::str;
+use tracing::info;
+
+use super::{Database, WriteBatch, COLUMN_FAMILIES};
+use crate::twitter::Tweet;
+
+/// Schema version of a database written by this version of Pigeon, which is version of the
+/// last migration.
+pub(crate) const SCHEMA_VERSION: u32 = 1;
+
+/// Key of schema version in meta column family.
+pub(crate) const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// An upgrade of stored data from the previous schema version to `version`.
+///
+/// Note: migrations must be idempotent, since data written by an older version
+/// may be imported into an upgraded database.
+pub(crate) struct Migration {
+    pub(crate) version: u32,
+    pub(crate) description: &'static str,
+    run: fn(&Database) -> Result<()>,
+}
+
+/// All migrations, in order of versions.
+const MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    description: "Record the time queued tweets are queued at",
+    run: record_queue_time,
+}];
+
+impl Database {
+    /// Returns schema version of database. Databases written before schema versioning
+    /// have no version, they're version 0 unless they're empty.
+    pub(crate) fn schema_version(&self) -> Result<u32> {
+        if let Some(version) = self.get_cf("meta", SCHEMA_VERSION_KEY)? {
+            return str::from_utf8(&version)?
+                .parse()
+                .with_context(|| "Invalid schema version in database");
+        }
+        let mut is_empty = true;
+        for cf in COLUMN_FAMILIES {
+            is_empty &= self.iterator_cf(cf)?.next().is_none();
+        }
+        Ok(if is_empty { SCHEMA_VERSION } else { 0 })
+    }
+
+    /// Returns migrations which are not applied to database yet.
+    pub(crate) fn pending_migrations(&self) -> Result<Vec<&'static Migration>> {
+        let version = self.schema_version()?;
+        if version > SCHEMA_VERSION {
+            bail!(
+                "Database schema version {version} is newer than {SCHEMA_VERSION} supported by this version of Pigeon, please upgrade Pigeon"
+            );
+        }
+        Ok(MIGRATIONS
+            .iter()
+            .filter(|migration| migration.version > version)
+            .collect())
+    }
+
+    /// Upgrades database to the latest schema version.
+    pub(crate) fn migrate(&self) -> Result<()> {
+        for migration in self.pending_migrations()? {
+            info!(
+                "Migrating database to schema version {}: {}",
+                migration.version, migration.description
+            );
+            (migration.run)(self).with_context(|| {
+                format!(
+                    "Failed to migrate database to schema version {}",
+                    migration.version
+                )
+            })?;
+            self.put_cf("meta", SCHEMA_VERSION_KEY, migration.version.to_string())?;
+        }
+        // Record version of a new database too.
+        if self.get_cf("meta", SCHEMA_VERSION_KEY)?.is_none() {
+            self.put_cf("meta", SCHEMA_VERSION_KEY, SCHEMA_VERSION.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+/// Tweets queued by older versions have no queue time, which `max_age` counts from.
+/// They're polled soon after they're created, so that's the time they're queued at.
+fn record_queue_time(database: &Database) -> Result<()> {
+    let mut batch = WriteBatch::default();
+    for entry in database.iterator_cf("timeline")? {
+        let (key, value) = entry?;
+        let tweet: Tweet = serde_json::from_slice(&value)
+            .with_context(|| format!("Invalid tweet {}", String::from_utf8_lossy(&key)))?;
+        if tweet.queued_at().is_none() {
+            let created_at = tweet.created_at.clone();
+            batch.put_cf(
+                "timeline",
+                key,
+                serde_json::to_vec(&tweet.queued(&created_at))?,
+            );
+        }
+    }
+    database.write(batch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MIGRATIONS, SCHEMA_VERSION};
+    use crate::{database::Database, twitter::Tweet};
+
+    #[test]
+    fn migrate() {
+        assert_eq!(
+            Some(SCHEMA_VERSION),
+            MIGRATIONS.last().map(|migration| migration.version)
+        );
+        let database = Database::memory();
+        // A new database is of the latest version.
+        assert_eq!(SCHEMA_VERSION, database.schema_version().unwrap());
+
+        // A database written before schema versioning.
+        database
+            .put_cf(
+                "timeline",
+                "TwitterDev:1001",
+                r#"{"id":"1001","created_at":"2024-01-01T00:00:00.000Z","text":"Hi"}"#,
+            )
+            .unwrap();
+        assert_eq!(0, database.schema_version().unwrap());
+        assert_eq!(1, database.pending_migrations().unwrap().len());
+
+        database.migrate().unwrap();
+        assert_eq!(SCHEMA_VERSION, database.schema_version().unwrap());
+        assert!(database.pending_migrations().unwrap().is_empty());
+        let value = database.get_cf("timeline", "TwitterDev:1001").unwrap();
+        let tweet: Tweet = serde_json::from_slice(&value.unwrap()).unwrap();
+        assert_eq!(Some("2024-01-01T00:00:00.000Z"), tweet.queued_at());
+
+        database.put_cf("meta", "schema_version", "99").unwrap();
+        assert!(database.migrate().is_err());
+    }
+}
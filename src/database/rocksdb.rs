@@ -0,0 +1,141 @@
+use anyhow::{anyhow, bail, Context, Result};
+use rocksdb::{
+    ColumnFamily, ColumnFamilyDescriptor, Direction, ErrorKind, IteratorMode, Options, DB,
+};
+use std::path::Path;
+
+use super::{lock, Entries, Storage, Write, WriteBatch, COLUMN_FAMILIES};
+
+/// Storage in RocksDB, which is the default.
+#[derive(Debug)]
+pub(crate) struct RocksDb(DB);
+
+impl RocksDb {
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let cfds: Vec<ColumnFamilyDescriptor> = COLUMN_FAMILIES
+            .iter()
+            .map(|&cf| ColumnFamilyDescriptor::new(cf, Options::default()))
+            .collect();
+
+        let mut db_opts = Options::default();
+        db_opts.create_if_missing(true);
+        db_opts.create_missing_column_families(true);
+
+        let db =
+            DB::open_cf_descriptors(&db_opts, path, cfds).map_err(|err| open_error(path, err))?;
+        Ok(Self(db))
+    }
+
+    /// Opens an existing database without RocksDB's lock, so that it's readable while
+    /// another process writes it. Writes return errors.
+    pub(crate) fn open_read_only(path: &Path) -> Result<Self> {
+        let db_opts = Options::default();
+        // Column families are created on demand, older databases may not have all of them.
+        let column_families: Vec<String> = DB::list_cf(&db_opts, path)
+            .map_err(|err| open_error(path, err))?
+            .into_iter()
+            .filter(|cf| COLUMN_FAMILIES.contains(&cf.as_str()))
+            .collect();
+        let db = DB::open_cf_for_read_only(&db_opts, path, column_families, false)
+            .map_err(|err| open_error(path, err))?;
+        Ok(Self(db))
+    }
+
+    fn cf_handle(&self, cf: &str) -> Result<&ColumnFamily> {
+        match self.0.cf_handle(cf) {
+            Some(cf_handle) => Ok(cf_handle),
+            None => bail!("no such column family: {cf}"),
+        }
+    }
+}
+
+/// RocksDB holds a lock file of its own, which is taken if an older version of Pigeon,
+/// without lock of database, is running.
+fn open_error(path: &Path, err: rocksdb::Error) -> anyhow::Error {
+    if err.kind() == ErrorKind::IOError && err.as_ref().contains("lock") {
+        return anyhow!(lock::in_use(path));
+    }
+    anyhow!(err).context(format!(
+        "Could not open RocksDB database: {}",
+        path.display()
+    ))
+}
+
+impl Storage for RocksDb {
+    fn put(&self, cf: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        Ok(self.0.put_cf(self.cf_handle(cf)?, key, value)?)
+    }
+
+    fn get(&self, cf: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.0
+            .get_cf(self.cf_handle(cf)?, key)
+            .with_context(|| "could not get value from column family")
+    }
+
+    fn delete(&self, cf: &str, key: &[u8]) -> Result<()> {
+        Ok(self.0.delete_cf(self.cf_handle(cf)?, key)?)
+    }
+
+    fn clear(&mut self, cf: &str) -> Result<()> {
+        // Dropping a column family is much faster than deleting its keys one by one.
+        self.0.drop_cf(cf)?;
+        Ok(self.0.create_cf(cf, &Options::default())?)
+    }
+
+    fn iter_from(&self, cf: &str, from: &[u8]) -> Result<Entries<'_>> {
+        let iterator = self
+            .0
+            .iterator_cf(
+                self.cf_handle(cf)?,
+                IteratorMode::From(from, Direction::Forward),
+            )
+            .map(|entry| Ok(entry?));
+        Ok(Box::new(iterator))
+    }
+
+    fn write(&self, batch: WriteBatch) -> Result<()> {
+        let mut write_batch = rocksdb::WriteBatch::default();
+        for write in &batch.0 {
+            match write {
+                Write::Put { cf, key, value } => {
+                    write_batch.put_cf(self.cf_handle(cf)?, key, value)
+                }
+                Write::Delete { cf, key } => write_batch.delete_cf(self.cf_handle(cf)?, key),
+                Write::DeleteRange { cf, from, to } => {
+                    write_batch.delete_range_cf(self.cf_handle(cf)?, from, to)
+                }
+            }
+        }
+        Ok(self.0.write(write_batch)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocksdb::{Options, DB};
+
+    use super::RocksDb;
+    use crate::database::{tests::check_storage, Access, Database};
+
+    #[test]
+    fn storage() {
+        let rocksdb_path =
+            std::env::temp_dir().join(format!("pigeon-rocksdb-{}", std::process::id()));
+        let database = Database::rocksdb(&rocksdb_path, Access::ReadWrite(None)).unwrap();
+        // Another process can't open it for writing, but for reading.
+        assert!(RocksDb::open(&rocksdb_path).is_err());
+        database.put_cf("state", "TwitterDev", "now").unwrap();
+        let read_only = Database::rocksdb(&rocksdb_path, Access::ReadOnly).unwrap();
+        assert_eq!(
+            Some(b"now".to_vec()),
+            read_only.get_cf("state", "TwitterDev").unwrap()
+        );
+        assert!(read_only.put_cf("state", "TwitterDev", "later").is_err());
+        drop(read_only);
+
+        database.delete_cf("state", "TwitterDev").unwrap();
+        check_storage(database);
+        DB::destroy(&Options::default(), &rocksdb_path).unwrap();
+        std::fs::remove_file(rocksdb_path.with_extension("lock")).unwrap();
+    }
+}
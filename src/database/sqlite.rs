@@ -0,0 +1,133 @@
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OpenFlags};
+use std::{path::Path, sync::Mutex};
+
+use super::{check_cf, paged, Entries, Storage, Write, WriteBatch, PAGE_SIZE};
+
+/// Storage in a SQLite database file, with all column families in one table.
+#[derive(Debug)]
+pub(crate) struct Sqlite(Mutex<Connection>);
+
+impl Sqlite {
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("Could not open SQLite database: {}", path.display()))?;
+        Self::init(connection)
+    }
+
+    /// Opens an existing database file, writes return errors.
+    pub(crate) fn open_read_only(path: &Path) -> Result<Self> {
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("Could not open SQLite database: {}", path.display()))?;
+        Ok(Self(Mutex::new(connection)))
+    }
+
+    fn init(connection: Connection) -> Result<Self> {
+        // BLOB keys are compared with memcmp, which orders them bytewise like RocksDB.
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS entries (
+                cf TEXT NOT NULL,
+                key BLOB NOT NULL,
+                value BLOB NOT NULL,
+                PRIMARY KEY (cf, key)
+            ) WITHOUT ROWID;",
+        )?;
+        Ok(Self(Mutex::new(connection)))
+    }
+
+    fn execute(&self, cf: &str, sql: &str, params: impl rusqlite::Params) -> Result<()> {
+        check_cf(cf)?;
+        // Unwrap it directly since the lock is never held across a panic.
+        self.0.lock().unwrap().execute(sql, params)?;
+        Ok(())
+    }
+}
+
+impl Storage for Sqlite {
+    fn put(&self, cf: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        self.execute(
+            cf,
+            "INSERT OR REPLACE INTO entries (cf, key, value) VALUES (?1, ?2, ?3)",
+            params![cf, key, value],
+        )
+    }
+
+    fn get(&self, cf: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        check_cf(cf)?;
+        let connection = self.0.lock().unwrap();
+        let mut statement =
+            connection.prepare_cached("SELECT value FROM entries WHERE cf = ?1 AND key = ?2")?;
+        let mut rows = statement.query(params![cf, key])?;
+        Ok(match rows.next()? {
+            Some(row) => Some(row.get(0)?),
+            None => None,
+        })
+    }
+
+    fn delete(&self, cf: &str, key: &[u8]) -> Result<()> {
+        self.execute(
+            cf,
+            "DELETE FROM entries WHERE cf = ?1 AND key = ?2",
+            params![cf, key],
+        )
+    }
+
+    fn clear(&mut self, cf: &str) -> Result<()> {
+        self.execute(cf, "DELETE FROM entries WHERE cf = ?1", params![cf])
+    }
+
+    fn iter_from(&self, cf: &str, from: &[u8]) -> Result<Entries<'_>> {
+        check_cf(cf)?;
+        let cf = cf.to_string();
+        // Entries are read a page at a time, so that the connection is not held while iterating.
+        Ok(paged(from, move |from| {
+            let connection = self.0.lock().unwrap();
+            let mut statement = connection.prepare_cached(
+                "SELECT key, value FROM entries WHERE cf = ?1 AND key >= ?2 ORDER BY key LIMIT ?3",
+            )?;
+            let entries = statement
+                .query_map(params![cf, from, PAGE_SIZE as i64], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(entries)
+        }))
+    }
+
+    fn write(&self, batch: WriteBatch) -> Result<()> {
+        batch.check()?;
+        let mut connection = self.0.lock().unwrap();
+        let transaction = connection.transaction()?;
+        for write in &batch.0 {
+            match write {
+                Write::Put { cf, key, value } => transaction.execute(
+                    "INSERT OR REPLACE INTO entries (cf, key, value) VALUES (?1, ?2, ?3)",
+                    params![cf, key, value],
+                )?,
+                Write::Delete { cf, key } => transaction.execute(
+                    "DELETE FROM entries WHERE cf = ?1 AND key = ?2",
+                    params![cf, key],
+                )?,
+                Write::DeleteRange { cf, from, to } => transaction.execute(
+                    "DELETE FROM entries WHERE cf = ?1 AND key >= ?2 AND key < ?3",
+                    params![cf, from, to],
+                )?,
+            };
+        }
+        Ok(transaction.commit()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::Sqlite;
+    use crate::database::{tests::check_storage, Database};
+
+    #[test]
+    fn storage() {
+        let sqlite = Sqlite::init(Connection::open_in_memory().unwrap()).unwrap();
+        check_storage(Database::new(sqlite));
+    }
+}
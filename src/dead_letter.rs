@@ -0,0 +1,40 @@
+use anyhow::{Context, Result};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::twitter::Tweet;
+
+/// A tweet that a destination permanently rejected, kept in dead_letter column family
+/// until it's retried or dropped.
+///
+/// It's keyed like a delivery, by timeline key and destination ID joined by a slash, so that
+/// other destinations of the tweet are not affected.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct DeadLetter {
+    pub(crate) tweet: Tweet,
+    /// HTTP status of the rejection.
+    pub(crate) status: u16,
+    /// Error description returned by destination.
+    pub(crate) error: String,
+    /// RFC3339 time the tweet is rejected at.
+    pub(crate) failed_at: String,
+}
+
+impl DeadLetter {
+    pub(crate) fn new(tweet: Tweet, status: StatusCode, error: String, failed_at: &str) -> Self {
+        Self {
+            tweet,
+            status: status.as_u16(),
+            error,
+            failed_at: failed_at.into(),
+        }
+    }
+
+    pub(crate) fn to_vec(&self) -> Result<Vec<u8>> {
+        serde_json::to_vec(self).with_context(|| "could not serialize dead letter to json")
+    }
+
+    pub(crate) fn from_slice(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes).with_context(|| "Invalid dead letter")
+    }
+}
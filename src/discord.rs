@@ -0,0 +1,164 @@
+use anyhow::{Context, Result};
+use reqwest::{Response, StatusCode};
+use reqwest_middleware::ClientWithMiddleware as Client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time;
+use tracing::{debug, warn};
+
+use crate::twitter::Tweet;
+
+/// Maximum attempts to execute a webhook when it keeps being rate limited.
+const MAX_ATTEMPTS: u8 = 5;
+
+/// A message posted through a Discord webhook.
+/// See: https://discord.com/developers/docs/resources/webhook#execute-webhook
+#[derive(Debug, Serialize)]
+pub(crate) struct Message {
+    /// Message text body.
+    content: String,
+    embeds: Vec<Embed>,
+}
+
+/// See: https://discord.com/developers/docs/resources/message#embed-object
+#[derive(Debug, Serialize)]
+struct Embed {
+    title: String,
+    /// Link to the original tweet.
+    url: String,
+    /// ISO8601 timestamp of the tweet.
+    timestamp: String,
+    author: Author,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<Image>,
+}
+
+#[derive(Debug, Serialize)]
+struct Author {
+    name: String,
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Image {
+    url: String,
+}
+
+/// Response body of a rate limited request.
+/// See: https://discord.com/developers/docs/topics/rate-limits#exceeding-a-rate-limit
+#[derive(Debug, Deserialize)]
+struct RateLimited {
+    /// The number of seconds to wait until sending another request.
+    retry_after: f64,
+}
+
+impl Message {
+    pub(crate) fn new(twitter_username: &str, tweet: Tweet) -> Self {
+        let embed = Embed {
+            title: "Open on Twitter".into(),
+            url: tweet.url(twitter_username),
+            timestamp: tweet.created_at,
+            author: Author {
+                name: format!("@{twitter_username}"),
+                url: format!("https://twitter.com/{twitter_username}"),
+            },
+            image: tweet
+                .media
+                .iter()
+                .find_map(|media| media.image_url())
+                .map(|url| Image { url: url.into() }),
+        };
+        Self {
+            content: tweet.text,
+            embeds: vec![embed],
+        }
+    }
+
+    /// Executes the webhook, waiting out Discord's rate limits when they are hit.
+    pub(crate) async fn send(&self, client: &Client, webhook_url: &str) -> Result<Response> {
+        let mut attempts = 1;
+        loop {
+            let response = client
+                .post(webhook_url)
+                // Wait for message creation so that errors are reported in response.
+                .query(&[("wait", "true")])
+                .json(self)
+                .send()
+                .await?;
+            if response.status() != StatusCode::TOO_MANY_REQUESTS || attempts == MAX_ATTEMPTS {
+                wait_for_bucket(&response).await;
+                return Ok(response);
+            }
+
+            let rate_limited: RateLimited = response
+                .json()
+                .await
+                .with_context(|| "Failed to deserialize Discord rate limit response")?;
+            warn!(
+                "Discord webhook rate limited, retry after {} seconds",
+                rate_limited.retry_after
+            );
+            time::sleep(Duration::from_secs_f64(rate_limited.retry_after)).await;
+            attempts += 1;
+        }
+    }
+}
+
+/// Sleeps until the rate limit bucket resets if there are no requests remaining in it.
+/// See: https://discord.com/developers/docs/topics/rate-limits#header-format
+async fn wait_for_bucket(response: &Response) {
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<f64>().ok())
+    };
+    if let (Some(remaining), Some(reset_after)) = (
+        header("X-RateLimit-Remaining"),
+        header("X-RateLimit-Reset-After"),
+    ) {
+        if remaining == 0.0 {
+            debug!("Discord rate limit bucket exhausted, wait {reset_after} seconds");
+            time::sleep(Duration::from_secs_f64(reset_after)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Message;
+    use crate::twitter::Tweet;
+
+    #[test]
+    fn message() {
+        let tweet: Tweet = serde_json::from_str(
+            r#"{
+              "id": "1587946527955329024",
+              "created_at": "2022-11-02T23:15:29.000Z",
+              "text": "We’re just a Tweet away",
+              "media": [
+                {
+                  "media_key": "3_1587519845523918848",
+                  "type": "photo",
+                  "url": "https://pbs.twimg.com/media/FgdmR8eXEAA7ILm.jpg"
+                }
+              ]
+            }"#,
+        )
+        .unwrap();
+        let message = serde_json::to_value(Message::new("TwitterDev", tweet)).unwrap();
+        assert_eq!("We’re just a Tweet away", message["content"]);
+        let embed = &message["embeds"][0];
+        assert_eq!(
+            "https://twitter.com/TwitterDev/status/1587946527955329024",
+            embed["url"]
+        );
+        assert_eq!("@TwitterDev", embed["author"]["name"]);
+        assert_eq!("2022-11-02T23:15:29.000Z", embed["timestamp"]);
+        assert_eq!(
+            "https://pbs.twimg.com/media/FgdmR8eXEAA7ILm.jpg",
+            embed["image"]["url"]
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use lettre::{
+    message::{Mailbox, MultiPart},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+use crate::{html, twitter::Tweet};
+
+/// Connection security to SMTP server.
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Encryption {
+    /// Upgrade plain connection with STARTTLS, default port is 587.
+    #[default]
+    Starttls,
+    /// Implicit TLS, default port is 465.
+    Tls,
+    /// Plain connection without encryption, default port is 25.
+    None,
+}
+
+/// An SMTP server that emails are sent through.
+#[derive(Debug)]
+pub(crate) struct Mailer<'a> {
+    pub(crate) host: &'a str,
+    pub(crate) port: Option<u16>,
+    pub(crate) tls: Encryption,
+    /// Credentials to authenticate with, if the server requires it.
+    pub(crate) credentials: Option<(&'a str, &'a str)>,
+}
+
+/// An email of one or many tweets.
+#[derive(Debug, Serialize)]
+pub(crate) struct Email {
+    subject: String,
+    text: String,
+    html: String,
+}
+
+impl Email {
+    /// Creates an email of tweets, which are grouped by Twitter username in the email body.
+    ///
+    /// Placeholders `{username}`, `{count}` and `{date}` in subject template are
+    /// replaced by Twitter usernames, number of tweets and current date.
+    pub(crate) fn new(subject: &str, tweets: &[(&str, Tweet)]) -> Self {
+        let mut usernames: Vec<&str> = vec![];
+        for (username, _) in tweets {
+            if !usernames.contains(username) {
+                usernames.push(username);
+            }
+        }
+
+        let mut text = String::new();
+        let mut html = String::new();
+        for username in &usernames {
+            let _ = write!(
+                html,
+                "<h3>@{username}</h3><table><tr><th>Time</th><th>Tweet</th></tr>"
+            );
+            for (_, tweet) in tweets.iter().filter(|(author, _)| author == username) {
+                let url = tweet.url(username);
+                let _ = write!(
+                    text,
+                    "@{username} · {}\n{}\n{url}\n\n",
+                    tweet.created_at, tweet.text
+                );
+                let _ = write!(
+                    html,
+                    "<tr><td>{}</td><td>{} <a href=\"{url}\">Open</a></td></tr>",
+                    tweet.created_at,
+                    html::escape(&tweet.text).replace('\n', "<br>"),
+                );
+            }
+            html.push_str("</table>");
+        }
+
+        let subject = subject
+            .replace("{username}", &usernames.join(", "))
+            .replace("{count}", &tweets.len().to_string())
+            .replace("{date}", &Utc::now().format("%Y-%m-%d").to_string());
+        Self {
+            subject,
+            text,
+            html,
+        }
+    }
+
+    pub(crate) async fn send(
+        self,
+        mailer: &Mailer<'_>,
+        sender: &str,
+        recipients: &[String],
+    ) -> Result<()> {
+        let mut builder = lettre::Message::builder()
+            .from(
+                sender
+                    .parse::<Mailbox>()
+                    .with_context(|| "Invalid email sender")?,
+            )
+            .subject(self.subject);
+        for recipient in recipients {
+            builder = builder.to(recipient
+                .parse::<Mailbox>()
+                .with_context(|| format!("Invalid email recipient: {recipient}"))?);
+        }
+        let message = builder
+            .multipart(MultiPart::alternative_plain_html(self.text, self.html))
+            .with_context(|| "Could not build email")?;
+
+        mailer.transport()?.send(message).await?;
+        Ok(())
+    }
+}
+
+impl<'a> Mailer<'a> {
+    fn transport(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>> {
+        let mut builder = match self.tls {
+            Encryption::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(self.host)?,
+            Encryption::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(self.host)?,
+            Encryption::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(self.host),
+        };
+        if let Some(port) = self.port {
+            builder = builder.port(port);
+        }
+        if let Some((user, password)) = self.credentials {
+            builder = builder.credentials(Credentials::new(user.into(), password.into()));
+        }
+        Ok(builder.build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    use super::{Email, Encryption, Mailer};
+    use crate::twitter::Tweet;
+
+    fn tweet(id: &str, text: &str) -> Tweet {
+        serde_json::from_value(json!({
+            "id": id,
+            "created_at": "2022-11-02T23:15:29.000Z",
+            "text": text
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn digest() {
+        let tweets = [
+            ("TwitterDev", tweet("1", "First")),
+            ("TwitterDev", tweet("2", "Second <3")),
+            ("jack", tweet("3", "Third")),
+        ];
+        let email = Email::new("{count} new tweets from {username}", &tweets);
+        assert_eq!("3 new tweets from TwitterDev, jack", email.subject);
+        assert_eq!(2, email.html.matches("<table>").count());
+        assert!(email.html.contains("<h3>@jack</h3>"));
+        assert!(email.html.contains("Second &lt;3"));
+        assert!(email.text.contains("https://twitter.com/jack/status/3"));
+    }
+
+    /// A local SMTP server which accepts a single email and returns its data.
+    async fn smtp_server(listener: TcpListener) -> String {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+
+        let mut data = String::new();
+        let mut in_data = false;
+        while let Some(line) = lines.next_line().await.unwrap() {
+            if in_data {
+                if line == "." {
+                    in_data = false;
+                    writer.write_all(b"250 OK\r\n").await.unwrap();
+                } else {
+                    data.push_str(&line);
+                    data.push('\n');
+                }
+                continue;
+            }
+            let reply: &[u8] = match line.split(' ').next().unwrap().to_uppercase().as_str() {
+                "EHLO" | "HELO" => b"250 localhost\r\n",
+                "DATA" => {
+                    in_data = true;
+                    b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                }
+                "QUIT" => {
+                    writer.write_all(b"221 Bye\r\n").await.unwrap();
+                    break;
+                }
+                _ => b"250 OK\r\n",
+            };
+            writer.write_all(reply).await.unwrap();
+        }
+        data
+    }
+
+    #[tokio::test]
+    async fn send() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(smtp_server(listener));
+
+        let mailer = Mailer {
+            host: "127.0.0.1",
+            port: Some(port),
+            tls: Encryption::None,
+            credentials: None,
+        };
+        Email::new(
+            "New tweet from @{username}",
+            &[("TwitterDev", tweet("1", "Hello"))],
+        )
+        .send(
+            &mailer,
+            "Pigeon <pigeon@localhost>",
+            &["team@localhost".into()],
+        )
+        .await
+        .unwrap();
+
+        let data = server.await.unwrap();
+        assert!(data.contains("Subject: New tweet from @TwitterDev"));
+        assert!(data.contains("To: team@localhost"));
+        assert!(data.contains("https://twitter.com/TwitterDev/status/1"));
+    }
+}
@@ -0,0 +1,7 @@
+/// Escapes text to be put in HTML, like Matrix formatted bodies and email bodies.
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
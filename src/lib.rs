@@ -1,9 +1,24 @@
 mod app;
+mod archive;
+mod cassette;
 mod commands;
 mod config;
 mod database;
+mod dead_letter;
+mod discord;
+mod email;
+mod html;
+mod mastodon;
+mod matrix;
+mod queue;
+mod retry;
+mod slack;
 mod telegram;
+mod thread;
 mod twitter;
+mod webhook;
 
 pub use app::App;
+pub use cassette::Cassette;
+pub use commands::{InfoFormat, InfoOptions, PreviewFormat};
 pub use config::Config;
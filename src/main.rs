@@ -1,5 +1,5 @@
-use clap::{Parser, Subcommand};
-use pigeon::{App, Config};
+use clap::{Args, Parser, Subcommand};
+use pigeon::{App, Cassette, Config, InfoOptions, PreviewFormat};
 use std::path::PathBuf;
 use tokio::{fs::File, io::AsyncReadExt};
 use tracing_subscriber::{
@@ -16,6 +16,14 @@ struct Cli {
     #[arg(short, long, default_value = "config.toml", value_name = "config.toml")]
     config_path: PathBuf,
 
+    /// Record HTTP requests and responses to a cassette directory, with secrets redacted
+    #[arg(long, global = true, value_name = "DIR", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Replay HTTP responses from a cassette directory instead of sending requests
+    #[arg(long, global = true, value_name = "DIR")]
+    replay: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Command,
 }
@@ -25,9 +33,102 @@ enum Command {
     /// Poll Twitter users' timeline
     Poll,
     /// Push timeline to Telegram channel(s)
-    Push,
+    Push {
+        /// Print messages that would be pushed instead of sending them
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Output format of a dry run
+        #[arg(long, value_enum, default_value_t, requires = "dry_run")]
+        format: PreviewFormat,
+    },
     /// Display overview information from Database
-    Info,
+    Info {
+        #[command(flatten)]
+        options: InfoOptions,
+
+        /// Open database read-only, so that it can be inspected while another pigeon process is running
+        #[arg(long)]
+        read_only: bool,
+    },
+    /// Export database to versioned JSON Lines
+    Export {
+        /// Output file path, default is stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Inspect and redrive tweets that destinations permanently rejected
+    Dlq {
+        #[command(subcommand)]
+        command: DlqCommand,
+    },
+    /// Manage database
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+    /// Queue tweets of an official Twitter archive ZIP for one push destination
+    ///
+    /// Photos are read from the archive's data/tweets_media/ directory, and destinations which
+    /// upload images, like Mastodon, upload them from there. Others link media from Twitter.
+    /// Tweets which are queued already are left as they are.
+    ImportArchive {
+        /// Twitter archive ZIP path
+        #[arg(value_name = "ZIP")]
+        input: PathBuf,
+
+        /// Destination to push tweets to, for example, "telegram:@some_channel",
+        /// or "telegram:@some_group/42" for a topic of a forum group
+        #[arg(long, value_name = "DESTINATION")]
+        to: String,
+
+        /// Twitter username of the archive, default is read from the archive
+        #[arg(long, value_name = "USERNAME")]
+        username: Option<String>,
+    },
+    /// Import database from JSON Lines written by export, overwriting existing keys
+    Import {
+        /// Input file path
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum DlqCommand {
+    /// List dead letters
+    List,
+    /// Queue dead letters to be pushed again, for example, after fixing config
+    Retry(DlqKeys),
+    /// Delete dead letters
+    Drop(DlqKeys),
+}
+
+#[derive(Args, Debug)]
+struct DlqKeys {
+    /// Keys of dead letters, as listed
+    #[arg(value_name = "KEY", required_unless_present = "all")]
+    keys: Vec<String>,
+
+    /// All dead letters
+    #[arg(long, conflicts_with = "keys")]
+    all: bool,
+}
+
+impl DlqKeys {
+    fn keys(&self) -> Option<&[String]> {
+        (!self.all).then_some(&self.keys)
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum DbCommand {
+    /// Upgrade database to the latest schema version
+    Migrate {
+        /// Only check whether migrations are pending, exit with status 1 if so
+        #[arg(long)]
+        check: bool,
+    },
 }
 
 #[tokio::main]
@@ -38,15 +139,61 @@ async fn main() -> anyhow::Result<()> {
         false => setup_logging("info"),
     }
     let config = load_config(cli.config_path).await?;
-    let mut app = App::new(config);
+    // Database commands open database without upgrading it first.
+    if let Command::Db { command } = cli.command {
+        return db(App::open(config)?, command);
+    }
+    if let Command::Info {
+        options,
+        read_only: true,
+    } = &cli.command
+    {
+        return App::open_read_only(config)?.info(options);
+    }
+
+    let mut app = App::new(config)?;
+    if let Some(dir) = cli.record {
+        app = app.cassette(Cassette::Record(dir))?;
+    } else if let Some(dir) = cli.replay {
+        app = app.cassette(Cassette::Replay(dir))?;
+    }
     match cli.command {
         Command::Poll => app.poll().await?,
-        Command::Push => app.push().await?,
-        Command::Info => app.info()?,
+        Command::Push { dry_run: false, .. } => app.push().await?,
+        Command::Push {
+            dry_run: true,
+            format,
+        } => app.push_dry_run(format).await?,
+        Command::Info { options, .. } => app.info(&options)?,
+        Command::Db { .. } => unreachable!("database commands are handled above"),
+        Command::Dlq { command } => match command {
+            DlqCommand::List => app.dlq_list()?,
+            DlqCommand::Retry(keys) => app.dlq_retry(keys.keys())?,
+            DlqCommand::Drop(keys) => app.dlq_drop(keys.keys())?,
+        },
+        Command::Export { output } => app.export(output.as_deref())?,
+        Command::Import { input } => app.import(&input)?,
+        Command::ImportArchive {
+            input,
+            to,
+            username,
+        } => app.import_archive(&input, &to, username)?,
     }
     Ok(())
 }
 
+fn db(app: App, command: DbCommand) -> anyhow::Result<()> {
+    match command {
+        DbCommand::Migrate { check: false } => app.migrate(),
+        DbCommand::Migrate { check: true } => {
+            if !app.check_migrations()? {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+    }
+}
+
 async fn load_config(path: PathBuf) -> anyhow::Result<Config> {
     let mut file = File::open(path).await?;
     let mut buf = String::new();
@@ -57,7 +204,8 @@ async fn load_config(path: PathBuf) -> anyhow::Result<Config> {
 }
 
 fn setup_logging(level: &str) {
-    let fmt_layer = tracing_subscriber::fmt::Layer::default();
+    // Log to stderr, so that output of commands can be piped.
+    let fmt_layer = tracing_subscriber::fmt::Layer::default().with_writer(std::io::stderr);
     let filter_layer = EnvFilter::try_from_default_env()
         .or_else(|_| EnvFilter::try_new(level))
         .unwrap();
@@ -0,0 +1,229 @@
+use anyhow::{bail, Context, Result};
+use reqwest::{
+    multipart::{Form, Part},
+    Response,
+};
+use reqwest_middleware::ClientWithMiddleware as Client;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use url::Url;
+
+use crate::twitter::{Media, Tweet};
+
+/// A status published to a Mastodon instance.
+/// See: https://docs.joinmastodon.org/methods/statuses/#create
+#[derive(Debug, Serialize)]
+pub(crate) struct Status {
+    /// Status text body.
+    status: String,
+    media_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    visibility: Option<Visibility>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to_id: Option<String>,
+    /// Tweet ID which idempotency key is derived from.
+    #[serde(skip)]
+    tweet_id: String,
+    /// Tweet media which has an image to upload.
+    #[serde(skip)]
+    images: Vec<Media>,
+}
+
+/// Visibility of published statuses.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Visibility {
+    Public,
+    Unlisted,
+    Private,
+    Direct,
+}
+
+/// A Mastodon account on an instance that statuses are published by.
+#[derive(Debug)]
+pub(crate) struct Account<'a> {
+    /// Instance base URL, for example, "https://mastodon.social".
+    pub(crate) instance: &'a str,
+    pub(crate) access_token: &'a str,
+}
+
+/// Response from media upload api.
+#[derive(Debug, Deserialize)]
+struct Attachment {
+    id: String,
+}
+
+impl Status {
+    pub(crate) fn new(
+        twitter_username: &str,
+        tweet: Tweet,
+        visibility: Option<Visibility>,
+        in_reply_to_id: Option<String>,
+    ) -> Self {
+        let status = format!("{}\n\n{}", tweet.text, tweet.url(twitter_username));
+        let images = tweet
+            .media
+            .into_iter()
+            .filter(|media| media.file.is_some() || media.image_url().is_some())
+            .collect();
+        Self {
+            status,
+            media_ids: vec![],
+            visibility,
+            in_reply_to_id,
+            tweet_id: tweet.id,
+            images,
+        }
+    }
+
+    /// Uploads media first, then publishes the status.
+    ///
+    /// The idempotency key is derived from tweet ID, so the instance doesn't
+    /// publish a status twice when an interrupted push is resumed.
+    pub(crate) async fn send(mut self, client: &Client, account: &Account<'_>) -> Result<Response> {
+        for media in &self.images {
+            let media_id = account.upload(client, media).await?;
+            self.media_ids.push(media_id);
+        }
+
+        debug!("Publish Mastodon status of tweet {}", self.tweet_id);
+        Ok(client
+            .post(account.endpoint("api/v1/statuses")?)
+            .bearer_auth(account.access_token)
+            .header("Idempotency-Key", format!("pigeon-{}", self.tweet_id))
+            .json(&self)
+            .send()
+            .await?)
+    }
+}
+
+impl<'a> Account<'a> {
+    /// Uploads the image of media as a media attachment, returns its ID. The image is
+    /// downloaded from Twitter, unless media carries its file.
+    /// See: https://docs.joinmastodon.org/methods/media/#v2
+    async fn upload(&self, client: &Client, media: &Media) -> Result<String> {
+        let (file_name, bytes) = match (&media.file, media.image_url()) {
+            (Some(file), _) => (file.name.clone(), file.bytes()?),
+            (None, Some(image_url)) => download(client, image_url).await?,
+            (None, None) => bail!("No image of media {}", media.media_key),
+        };
+
+        let form = Form::new().part("file", Part::bytes(bytes).file_name(file_name));
+        let attachment: Attachment = client
+            .post(self.endpoint("api/v2/media")?)
+            .bearer_auth(self.access_token)
+            .multipart(form)
+            .send()
+            .await
+            .and_then(|response| Ok(response.error_for_status()?))
+            .with_context(|| "Failed to upload media to Mastodon")?
+            .json()
+            .await
+            .with_context(|| "Failed to deserialize json response")?;
+        Ok(attachment.id)
+    }
+
+    fn endpoint(&self, path: &str) -> Result<Url> {
+        let base_url =
+            Url::parse(self.instance).with_context(|| "Could not parse Mastodon instance URL")?;
+        base_url
+            .join(path)
+            .with_context(|| "Could not parse Mastodon api path")
+    }
+}
+
+/// Downloads an image, returns its file name and content.
+async fn download(client: &Client, image_url: &str) -> Result<(String, Vec<u8>)> {
+    let image = client
+        .get(image_url)
+        .send()
+        .await
+        .and_then(|response| Ok(response.error_for_status()?))
+        .with_context(|| format!("Failed to download image: {image_url}"))?;
+    let file_name = image
+        .url()
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .unwrap_or("image")
+        .to_string();
+    let bytes = image.bytes().await?;
+    Ok((file_name, bytes.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest_middleware::ClientWithMiddleware as Client;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_partial_json, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::{Account, Status, Visibility};
+    use crate::twitter::Tweet;
+
+    #[tokio::test]
+    async fn send() {
+        let instance = MockServer::start().await;
+        let tweet: Tweet = serde_json::from_value(json!({
+            "id": "1587946527955329024",
+            "created_at": "2022-11-02T23:15:29.000Z",
+            "text": "We’re just a Tweet away",
+            "media": [
+                {
+                    "media_key": "3_1587519845523918848",
+                    "type": "photo",
+                    "url": format!("{}/media/FgdmR8eXEAA7ILm.jpg", instance.uri())
+                },
+                // A photo of a Twitter archive, which Twitter no longer serves.
+                {
+                    "media_key": "3_1587519845523918849",
+                    "type": "photo",
+                    "url": format!("{}/media/gone.jpg", instance.uri()),
+                    "file": {"name": "1587946527955329024-gone.jpg", "base64": "/9g="}
+                }
+            ]
+        }))
+        .unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/media/FgdmR8eXEAA7ILm.jpg"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(vec![0xFF, 0xD8], "image/jpeg"))
+            .expect(1)
+            .mount(&instance)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/v2/media"))
+            .and(header("Authorization", "Bearer secret"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "22348641"})))
+            .expect(2)
+            .mount(&instance)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/statuses"))
+            .and(header("Idempotency-Key", "pigeon-1587946527955329024"))
+            .and(body_partial_json(json!({
+                "status": "We’re just a Tweet away\n\nhttps://twitter.com/TwitterDev/status/1587946527955329024",
+                "media_ids": ["22348641", "22348641"],
+                "visibility": "unlisted",
+                "in_reply_to_id": "109308234123"
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "109308234567"})))
+            .expect(1)
+            .mount(&instance)
+            .await;
+
+        let account = Account {
+            instance: &instance.uri(),
+            access_token: "secret",
+        };
+        let status = Status::new(
+            "TwitterDev",
+            tweet,
+            Some(Visibility::Unlisted),
+            Some("109308234123".into()),
+        );
+        let response = status.send(&Client::default(), &account).await.unwrap();
+        assert!(response.status().is_success());
+    }
+}
@@ -0,0 +1,252 @@
+use anyhow::{anyhow, Context, Result};
+use reqwest::{header::CONTENT_TYPE, Response};
+use reqwest_middleware::ClientWithMiddleware as Client;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use url::Url;
+
+use crate::{html, twitter::Tweet};
+
+/// A room message sent to a Matrix room.
+/// See: https://spec.matrix.org/v1.9/client-server-api/#mroommessage
+#[derive(Debug, Serialize)]
+pub(crate) struct Message {
+    /// Tweet ID which transaction IDs are derived from.
+    tweet_id: String,
+    text: Text,
+    /// Image URLs of tweet media.
+    images: Vec<String>,
+}
+
+/// See: https://spec.matrix.org/v1.9/client-server-api/#mtext
+#[derive(Debug, Serialize)]
+struct Text {
+    msgtype: &'static str,
+    /// Plain text body for clients that don't render HTML.
+    body: String,
+    format: &'static str,
+    formatted_body: String,
+}
+
+/// See: https://spec.matrix.org/v1.9/client-server-api/#mimage
+#[derive(Debug, Serialize)]
+struct Image<'a> {
+    msgtype: &'static str,
+    body: &'a str,
+    url: &'a str,
+}
+
+/// Response from media repository upload api.
+#[derive(Debug, Deserialize)]
+struct Upload {
+    content_uri: String,
+}
+
+/// A Matrix room on a homeserver that messages are sent to.
+#[derive(Debug)]
+pub(crate) struct Room<'a> {
+    /// Homeserver base URL, for example, "https://matrix.org".
+    pub(crate) homeserver: &'a str,
+    /// Room ID, for example, "!abc:matrix.org".
+    pub(crate) room_id: &'a str,
+    pub(crate) access_token: &'a str,
+}
+
+impl Message {
+    pub(crate) fn new(twitter_username: &str, tweet: Tweet) -> Self {
+        let url = tweet.url(twitter_username);
+        let text = Text {
+            msgtype: "m.text",
+            body: format!("{}\n\n{url}", tweet.text),
+            format: "org.matrix.custom.html",
+            formatted_body: format!(
+                "<p>{}</p><p><a href=\"{url}\">@{twitter_username}</a> · {}</p>",
+                html::escape(&tweet.text).replace('\n', "<br>"),
+                tweet.created_at
+            ),
+        };
+        let images = tweet
+            .media
+            .iter()
+            .filter_map(|media| media.image_url())
+            .map(String::from)
+            .collect();
+        Self {
+            tweet_id: tweet.id,
+            text,
+            images,
+        }
+    }
+
+    /// Sends the text event first, then uploads and sends every image.
+    ///
+    /// Transaction IDs are derived from tweet ID, so the homeserver drops
+    /// events that were already sent by an interrupted push.
+    pub(crate) async fn send(&self, client: &Client, room: &Room<'_>) -> Result<Response> {
+        let txn_id = format!("pigeon-{}", self.tweet_id);
+        let response = room.send(client, &txn_id, &self.text).await?;
+        if !response.status().is_success() {
+            return Ok(response);
+        }
+
+        for (i, image_url) in self.images.iter().enumerate() {
+            let content_uri = room.upload(client, image_url).await?;
+            let image = Image {
+                msgtype: "m.image",
+                body: image_url,
+                url: &content_uri,
+            };
+            let response = room.send(client, &format!("{txn_id}-{i}"), &image).await?;
+            if !response.status().is_success() {
+                return Ok(response);
+            }
+        }
+        Ok(response)
+    }
+}
+
+impl<'a> Room<'a> {
+    /// Sends an `m.room.message` event to the room.
+    /// See: https://spec.matrix.org/v1.9/client-server-api/#put_matrixclientv3roomsroomidsendeventtypetxnid
+    async fn send<T: Serialize>(
+        &self,
+        client: &Client,
+        txn_id: &str,
+        content: &T,
+    ) -> Result<Response> {
+        let mut url = self.endpoint("_matrix/client/v3/rooms")?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("Matrix homeserver URL cannot be a base"))?
+            .extend([self.room_id, "send", "m.room.message", txn_id]);
+        debug!("Send Matrix event, txn_id: {txn_id}");
+        Ok(client
+            .put(url)
+            .bearer_auth(self.access_token)
+            .json(content)
+            .send()
+            .await?)
+    }
+
+    /// Downloads an image and uploads it to the media repository, returns its `mxc://` URI.
+    /// See: https://spec.matrix.org/v1.9/client-server-api/#post_matrixmediav3upload
+    async fn upload(&self, client: &Client, image_url: &str) -> Result<String> {
+        let image = client
+            .get(image_url)
+            .send()
+            .await
+            .and_then(|response| Ok(response.error_for_status()?))
+            .with_context(|| format!("Failed to download image: {image_url}"))?;
+        let content_type = image
+            .headers()
+            .get(CONTENT_TYPE)
+            .cloned()
+            .unwrap_or_else(|| "application/octet-stream".parse().unwrap());
+        let bytes = image.bytes().await?;
+
+        let upload: Upload = client
+            .post(self.endpoint("_matrix/media/v3/upload")?)
+            .bearer_auth(self.access_token)
+            .header(CONTENT_TYPE, content_type)
+            .body(bytes)
+            .send()
+            .await
+            .and_then(|response| Ok(response.error_for_status()?))
+            .with_context(|| "Failed to upload image to Matrix media repository")?
+            .json()
+            .await
+            .with_context(|| "Failed to deserialize json response")?;
+        Ok(upload.content_uri)
+    }
+
+    fn endpoint(&self, path: &str) -> Result<Url> {
+        let base_url =
+            Url::parse(self.homeserver).with_context(|| "Could not parse Matrix homeserver URL")?;
+        base_url
+            .join(path)
+            .with_context(|| "Could not parse Matrix api path")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest_middleware::ClientWithMiddleware as Client;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_partial_json, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::{Message, Room};
+    use crate::twitter::Tweet;
+
+    #[tokio::test]
+    async fn send() {
+        let homeserver = MockServer::start().await;
+        let tweet: Tweet = serde_json::from_value(json!({
+            "id": "1587946527955329024",
+            "created_at": "2022-11-02T23:15:29.000Z",
+            "text": "Tips & tricks <3",
+            "media": [
+                {
+                    "media_key": "3_1587519845523918848",
+                    "type": "photo",
+                    "url": format!("{}/media/FgdmR8eXEAA7ILm.jpg", homeserver.uri())
+                }
+            ]
+        }))
+        .unwrap();
+
+        Mock::given(method("PUT"))
+            .and(path(
+                "/_matrix/client/v3/rooms/!room:localhost/send/m.room.message/pigeon-1587946527955329024",
+            ))
+            .and(header("Authorization", "Bearer secret"))
+            .and(body_partial_json(json!({
+                "msgtype": "m.text",
+                "formatted_body": "<p>Tips &amp; tricks &lt;3</p><p><a href=\"https://twitter.com/TwitterDev/status/1587946527955329024\">@TwitterDev</a> · 2022-11-02T23:15:29.000Z</p>"
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"event_id": "$1"})))
+            .expect(1)
+            .mount(&homeserver)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/media/FgdmR8eXEAA7ILm.jpg"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(vec![0xFF, 0xD8], "image/jpeg"))
+            .expect(1)
+            .mount(&homeserver)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/_matrix/media/v3/upload"))
+            .and(header("Content-Type", "image/jpeg"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"content_uri": "mxc://localhost/abc"})),
+            )
+            .expect(1)
+            .mount(&homeserver)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path(
+                "/_matrix/client/v3/rooms/!room:localhost/send/m.room.message/pigeon-1587946527955329024-0",
+            ))
+            .and(body_partial_json(json!({
+                "msgtype": "m.image",
+                "url": "mxc://localhost/abc"
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"event_id": "$2"})))
+            .expect(1)
+            .mount(&homeserver)
+            .await;
+
+        let room = Room {
+            homeserver: &homeserver.uri(),
+            room_id: "!room:localhost",
+            access_token: "secret",
+        };
+        let response = Message::new("TwitterDev", tweet)
+            .send(&Client::default(), &room)
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+    }
+}
@@ -0,0 +1,260 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use std::{collections::BTreeMap, str};
+use tracing::{debug, warn};
+
+use crate::{
+    config::DropPolicy,
+    database::{Database, WriteBatch},
+    twitter::Tweet,
+};
+
+/// Caps of tweets queued in timeline column family, applied after each poll.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Limits {
+    pub(crate) max_queue_per_user: Option<usize>,
+    pub(crate) max_age: Option<Duration>,
+    pub(crate) max_total_bytes: Option<u64>,
+    pub(crate) drop_policy: DropPolicy,
+}
+
+/// A tweet queued in timeline column family.
+#[derive(Debug)]
+struct Queued {
+    key: Box<[u8]>,
+    username: String,
+    queued_at: DateTime<Utc>,
+    created_at: DateTime<Utc>,
+    bytes: u64,
+}
+
+/// Returns bytes a timeline entry takes, which `max_total_bytes` limits.
+pub(crate) fn size(key: &[u8], value: &[u8]) -> u64 {
+    (key.len() + value.len()) as u64
+}
+
+/// Drops queued tweets beyond limits along with their deliveries, returns how many are dropped.
+///
+/// Tweets queued earlier than `max_age` are always dropped, while `max_queue_per_user` and
+/// `max_total_bytes` drop the earliest or latest queued tweets according to drop policy.
+pub(crate) fn enforce(database: &Database, limits: &Limits, now: DateTime<Utc>) -> Result<usize> {
+    if limits.max_queue_per_user.is_none()
+        && limits.max_age.is_none()
+        && limits.max_total_bytes.is_none()
+    {
+        return Ok(0);
+    }
+
+    let queue = read(database)?;
+    // Reason a tweet is dropped for, indexed like queue.
+    let mut dropped: Vec<Option<&str>> = vec![None; queue.len()];
+    // Tweets in the order they're dropped by policy.
+    let order: Vec<usize> = match limits.drop_policy {
+        DropPolicy::Oldest => (0..queue.len()).collect(),
+        DropPolicy::Newest => (0..queue.len()).rev().collect(),
+    };
+
+    if let Some(max_age) = limits.max_age {
+        for (i, queued) in queue.iter().enumerate() {
+            if queued.queued_at + max_age < now {
+                dropped[i] = Some("max_age");
+            }
+        }
+    }
+    if let Some(max_queue_per_user) = limits.max_queue_per_user {
+        let mut queued: BTreeMap<&str, usize> = BTreeMap::new();
+        for (i, tweet) in queue.iter().enumerate() {
+            if dropped[i].is_none() {
+                *queued.entry(&tweet.username).or_default() += 1;
+            }
+        }
+        for &i in &order {
+            let count = queued.get_mut(queue[i].username.as_str());
+            if let Some(count) = count.filter(|count| **count > max_queue_per_user) {
+                if dropped[i].is_none() {
+                    dropped[i] = Some("max_queue_per_user");
+                    *count -= 1;
+                }
+            }
+        }
+    }
+    if let Some(max_total_bytes) = limits.max_total_bytes {
+        let mut total_bytes: u64 = queue
+            .iter()
+            .zip(&dropped)
+            .filter(|(_, reason)| reason.is_none())
+            .map(|(queued, _)| queued.bytes)
+            .sum();
+        for &i in &order {
+            if total_bytes <= max_total_bytes {
+                break;
+            }
+            if dropped[i].is_none() {
+                dropped[i] = Some("max_total_bytes");
+                total_bytes -= queue[i].bytes;
+            }
+        }
+    }
+
+    // Number of dropped tweets of each user and reason, for logging.
+    let mut summary: BTreeMap<(&str, &str), usize> = BTreeMap::new();
+    let mut batch = WriteBatch::default();
+    for (queued, reason) in queue.iter().zip(&dropped) {
+        let Some(reason) = reason else {
+            continue;
+        };
+        debug!(
+            "Drop queued tweet exceeding {reason}: {}",
+            String::from_utf8_lossy(&queued.key)
+        );
+        *summary.entry((&queued.username, reason)).or_default() += 1;
+        // Delivery keys of a tweet are prefixed by its timeline key and a slash.
+        let from = [&queued.key[..], b"/"].concat();
+        let to = [&queued.key[..], b"0"].concat();
+        batch.delete_range_cf("delivery", from, to);
+        batch.delete_cf("timeline", &queued.key);
+    }
+    database.write(batch)?;
+
+    for ((username, reason), count) in &summary {
+        warn!("Dropped {count} queued tweets of {username} exceeding {reason}.");
+    }
+    Ok(summary.values().sum())
+}
+
+/// Reads queued tweets, earliest queued first, and then oldest first.
+fn read(database: &Database) -> Result<Vec<Queued>> {
+    let mut queue = vec![];
+    for entry in database.iterator_cf("timeline")? {
+        let (key, value) = entry?;
+        let key_str = str::from_utf8(&key)?;
+        // Unwrap it directly since we're sure it's Some(&str).
+        let (username, _) = key_str.split_once(':').unwrap();
+        let tweet: Tweet = serde_json::from_slice(&value)?;
+        let created_at = DateTime::parse_from_rfc3339(&tweet.created_at)
+            .with_context(|| format!("Invalid creation time of tweet {key_str}"))?
+            .to_utc();
+        let queued_at = tweet
+            .queued_at()
+            .with_context(|| format!("No queue time of tweet {key_str}"))?;
+        let queued_at = DateTime::parse_from_rfc3339(queued_at)
+            .with_context(|| format!("Invalid queue time of tweet {key_str}"))?
+            .to_utc();
+        queue.push(Queued {
+            username: username.into(),
+            queued_at,
+            created_at,
+            bytes: size(&key, &value),
+            key,
+        });
+    }
+    queue.sort_by(|a, b| {
+        (a.queued_at, a.created_at, &a.key).cmp(&(b.queued_at, b.created_at, &b.key))
+    });
+    Ok(queue)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration};
+
+    use super::{enforce, Limits};
+    use crate::{config::DropPolicy, database::Database};
+
+    fn queue() -> Database {
+        let database = Database::memory();
+        for (key, day) in [
+            ("TwitterDev:1001", 1),
+            ("TwitterDev:1002", 2),
+            ("TwitterDev:1003", 3),
+            ("jack:2001", 4),
+        ] {
+            let tweet = serde_json::json!({
+                "id": key.split_once(':').unwrap().1,
+                "created_at": format!("2024-01-0{day}T00:00:00Z"),
+                "text": "Hi",
+                "queued_at": format!("2024-01-0{day}T00:00:00Z"),
+            });
+            database.put_cf("timeline", key, tweet.to_string()).unwrap();
+            database
+                .put_cf("delivery", format!("{key}/discord:xyz"), "{}")
+                .unwrap();
+        }
+        database
+    }
+
+    fn keys(database: &Database, cf: &str) -> Vec<String> {
+        database
+            .iterator_cf(cf)
+            .unwrap()
+            .map(|entry| String::from_utf8(entry.unwrap().0.to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn enforce_limits() {
+        let now = DateTime::parse_from_rfc3339("2024-01-05T00:00:00Z")
+            .unwrap()
+            .to_utc();
+
+        let database = queue();
+        assert_eq!(0, enforce(&database, &Limits::default(), now).unwrap());
+        let limits = Limits {
+            max_age: Some(Duration::days(3)),
+            max_queue_per_user: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(2, enforce(&database, &limits, now).unwrap());
+        assert_eq!(
+            vec!["TwitterDev:1003", "jack:2001"],
+            keys(&database, "timeline")
+        );
+        assert_eq!(
+            vec!["TwitterDev:1003/discord:xyz", "jack:2001/discord:xyz"],
+            keys(&database, "delivery")
+        );
+
+        let database = queue();
+        let limits = Limits {
+            max_queue_per_user: Some(2),
+            drop_policy: DropPolicy::Newest,
+            ..Default::default()
+        };
+        assert_eq!(1, enforce(&database, &limits, now).unwrap());
+        assert_eq!(
+            vec!["TwitterDev:1001", "TwitterDev:1002", "jack:2001"],
+            keys(&database, "timeline")
+        );
+
+        // Each entry takes about 100 bytes, only one of them fits.
+        let database = queue();
+        let limits = Limits {
+            max_total_bytes: Some(150),
+            ..Default::default()
+        };
+        assert_eq!(3, enforce(&database, &limits, now).unwrap());
+        assert_eq!(vec!["jack:2001"], keys(&database, "timeline"));
+
+        // Tweets age from the time they're queued at, like ones imported from an archive.
+        let database = queue();
+        let tweet = serde_json::json!({
+            "id": "20",
+            "created_at": "2006-03-21T00:00:00Z",
+            "text": "Hi",
+            "queued_at": "2024-01-04T00:00:00Z",
+        });
+        database
+            .put_cf("timeline", "TwitterDev:20", tweet.to_string())
+            .unwrap();
+        let limits = Limits {
+            max_age: Some(Duration::days(3)),
+            max_queue_per_user: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(2, enforce(&database, &limits, now).unwrap());
+        assert_eq!(
+            vec!["TwitterDev:1003", "TwitterDev:20", "jack:2001"],
+            keys(&database, "timeline")
+        );
+    }
+}
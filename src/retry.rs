@@ -0,0 +1,212 @@
+use async_trait::async_trait;
+use http::Extensions;
+use reqwest::{Request, Response, StatusCode};
+use reqwest_middleware::{Middleware, Next};
+use std::{
+    hash::{BuildHasher, Hasher, RandomState},
+    time::Duration,
+};
+use tokio::time;
+use tracing::{field, info_span, warn, Instrument, Span};
+
+/// Longest delay between two attempts, however many retries there are.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// How requests failing with transient errors are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RetryPolicy {
+    /// Attempts of a request including the first one, 1 disables retries.
+    pub(crate) max_attempts: u32,
+    /// Delay before the first retry, doubled on each following retry.
+    pub(crate) base_delay: Duration,
+    /// Whether delays are randomized between half and all of them,
+    /// so that retries of many requests are spread out.
+    pub(crate) jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_secs(1),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns delay before retrying a request which failed `attempt` times.
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(MAX_DELAY);
+        if !self.jitter {
+            return delay;
+        }
+        // A randomly seeded hasher is random enough for spreading retries out.
+        let random = RandomState::new().build_hasher().finish();
+        delay / 2 + delay.mul_f64((random % 1000) as f64 / 2000.0)
+    }
+}
+
+/// Middleware retrying requests that fail with transient errors, with exponential backoff.
+///
+/// Requests are retried if they're never sent, for example, on DNS or connection errors.
+/// Idempotent requests, like timeline requests, are also retried on timeouts, connection
+/// resets and gateway errors. Others, like Telegram messages, are not, since the server
+/// may have handled them already.
+#[derive(Debug)]
+pub(crate) struct Retry(pub(crate) RetryPolicy);
+
+#[async_trait]
+impl Middleware for Retry {
+    async fn handle(
+        &self,
+        request: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let idempotent = request.method().is_idempotent();
+        // Only host is recorded, since URLs may carry tokens, like Telegram Bot API does.
+        let span = info_span!(
+            "retry",
+            method = %request.method(),
+            host = request.url().host_str().unwrap_or_default(),
+            attempt = field::Empty,
+        );
+        async move {
+            let mut attempt = 1;
+            loop {
+                Span::current().record("attempt", attempt);
+                // Requests with streaming bodies can't be cloned, so they're sent only once.
+                let retry = match attempt < self.0.max_attempts {
+                    true => request.try_clone(),
+                    false => None,
+                };
+                let Some(retry) = retry else {
+                    return next.run(request, extensions).await;
+                };
+
+                let result = next.clone().run(retry, extensions).await;
+                if !is_transient(&result, idempotent) {
+                    return result;
+                }
+                let delay = self.0.delay(attempt);
+                match &result {
+                    Ok(response) => warn!(
+                        "Request not successful, response status: {}, retry in {delay:?}",
+                        response.status()
+                    ),
+                    Err(err) => warn!("Request failed: {err}, retry in {delay:?}"),
+                }
+                time::sleep(delay).await;
+                attempt += 1;
+            }
+        }
+        .instrument(span)
+        .await
+    }
+}
+
+/// Whether a request may succeed if it's sent again.
+fn is_transient(result: &reqwest_middleware::Result<Response>, idempotent: bool) -> bool {
+    match result {
+        Ok(response) => {
+            idempotent
+                && matches!(
+                    response.status(),
+                    StatusCode::BAD_GATEWAY
+                        | StatusCode::SERVICE_UNAVAILABLE
+                        | StatusCode::GATEWAY_TIMEOUT
+                )
+        }
+        Err(reqwest_middleware::Error::Reqwest(err)) => {
+            err.is_connect() || (idempotent && (err.is_timeout() || err.is_request()))
+        }
+        // Errors of other middlewares, like a missing cassette interaction, are not transient.
+        Err(reqwest_middleware::Error::Middleware(_)) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest_middleware::ClientBuilder;
+    use std::time::Duration;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::{Retry, RetryPolicy};
+
+    #[test]
+    fn delay() {
+        let policy = RetryPolicy {
+            jitter: false,
+            ..Default::default()
+        };
+        assert_eq!(Duration::from_secs(1), policy.delay(1));
+        assert_eq!(Duration::from_secs(4), policy.delay(3));
+        assert_eq!(Duration::from_secs(60), policy.delay(10));
+
+        let policy = RetryPolicy::default();
+        for _ in 0..10 {
+            let delay = policy.delay(2);
+            assert!(Duration::from_secs(1) <= delay && delay <= Duration::from_secs(2));
+        }
+    }
+
+    #[tokio::test]
+    async fn retry() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/timeline"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/timeline"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        // Messages are not idempotent, so they're sent once.
+        Mock::given(method("POST"))
+            .and(path("/message"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(Retry(policy))
+            .build();
+        let response = client
+            .get(format!("{}/timeline", server.uri()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(200, response.status());
+        let response = client
+            .post(format!("{}/message", server.uri()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(503, response.status());
+
+        // Nothing listens on the port of a dropped listener, so the request is never sent.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let started_at = std::time::Instant::now();
+        assert!(client.post(uri).send().await.unwrap_err().is_connect());
+        assert!(started_at.elapsed() >= Duration::from_millis(15));
+    }
+}
@@ -0,0 +1,309 @@
+use anyhow::{bail, Context, Result};
+use reqwest::{header::RETRY_AFTER, Response, StatusCode};
+use reqwest_middleware::ClientWithMiddleware as Client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time;
+use tracing::warn;
+use url::Url;
+
+use crate::twitter::Tweet;
+
+/// Slack Web API base URL.
+pub(crate) const API_BASE: &str = "https://slack.com/api/";
+
+/// Maximum attempts to post a message when it keeps being rate limited.
+const MAX_ATTEMPTS: u8 = 5;
+
+/// A Slack message rendered with Block Kit.
+/// See: https://api.slack.com/reference/block-kit/blocks
+#[derive(Debug, Serialize)]
+pub(crate) struct Message {
+    /// Channel to post to, only used by `chat.postMessage`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channel: Option<String>,
+    /// Fallback text shown in notifications.
+    text: String,
+    blocks: Vec<Block>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Block {
+    Section { text: Text },
+    Context { elements: Vec<Text> },
+    Image { image_url: String, alt_text: String },
+}
+
+/// See: https://api.slack.com/reference/block-kit/composition-objects#text
+#[derive(Debug, Serialize)]
+struct Text {
+    #[serde(rename = "type")]
+    typ: &'static str,
+    text: String,
+}
+
+/// Where a Slack message is posted to.
+#[derive(Debug)]
+pub(crate) enum Channel<'a> {
+    /// An incoming webhook URL.
+    /// See: https://api.slack.com/messaging/webhooks
+    Webhook(&'a str),
+    /// A channel posted to by a bot with `chat.postMessage` of Web API at `api`.
+    /// See: https://api.slack.com/methods/chat.postMessage
+    Bot {
+        api: &'a Url,
+        token: &'a str,
+        channel: &'a str,
+    },
+}
+
+/// Response from Slack Web API methods, which report errors with status OK.
+#[derive(Debug, Deserialize)]
+struct ApiResponse {
+    ok: bool,
+    error: Option<String>,
+}
+
+impl Message {
+    pub(crate) fn new(twitter_username: &str, tweet: Tweet) -> Self {
+        let url = tweet.url(twitter_username);
+        let mut blocks = vec![
+            Block::Section {
+                text: Text::mrkdwn(format!("{}\n<{url}|Open on Twitter>", escape(&tweet.text))),
+            },
+            Block::Context {
+                elements: vec![Text::mrkdwn(format!(
+                    "<https://twitter.com/{twitter_username}|@{twitter_username}> · {}",
+                    tweet.created_at
+                ))],
+            },
+        ];
+        blocks.extend(
+            tweet
+                .media
+                .iter()
+                .filter_map(|media| media.image_url())
+                .map(|image_url| Block::Image {
+                    image_url: image_url.into(),
+                    alt_text: format!("Media of @{twitter_username}'s tweet"),
+                }),
+        );
+        Self {
+            channel: None,
+            text: tweet.text,
+            blocks,
+        }
+    }
+
+    /// Posts message to Slack, waiting out `Retry-After` when rate limited.
+    pub(crate) async fn send(mut self, client: &Client, channel: Channel<'_>) -> Result<Response> {
+        let request = match channel {
+            Channel::Webhook(webhook_url) => client.post(webhook_url),
+            Channel::Bot {
+                api,
+                token,
+                channel,
+            } => {
+                self.channel = Some(channel.into());
+                let endpoint = api
+                    .join("chat.postMessage")
+                    .with_context(|| "Failed to parse Slack chat.postMessage endpoint")?;
+                client.post(endpoint).bearer_auth(token)
+            }
+        }
+        .json(&self);
+
+        let mut attempts = 1;
+        let response = loop {
+            // Unwrap it directly since request body is not a stream.
+            let response = request.try_clone().unwrap().send().await?;
+            if response.status() != StatusCode::TOO_MANY_REQUESTS || attempts == MAX_ATTEMPTS {
+                break response;
+            }
+
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(1);
+            warn!("Slack rate limited, retry after {retry_after} seconds");
+            time::sleep(Duration::from_secs(retry_after)).await;
+            attempts += 1;
+        };
+        if let Channel::Bot { .. } = channel {
+            return check_api_response(response).await;
+        }
+        Ok(response)
+    }
+}
+
+impl Text {
+    fn mrkdwn(text: String) -> Self {
+        Self {
+            typ: "mrkdwn",
+            text,
+        }
+    }
+}
+
+/// Web API methods respond with status OK and `"ok": false` on errors.
+async fn check_api_response(response: Response) -> Result<Response> {
+    if !response.status().is_success() {
+        return Ok(response);
+    }
+    // Keep response bytes to rebuild the response for caller.
+    let status = response.status();
+    let bytes = response.bytes().await?;
+    let api_response: ApiResponse =
+        serde_json::from_slice(&bytes).with_context(|| "Failed to deserialize json response")?;
+    if !api_response.ok {
+        bail!(
+            "Slack chat.postMessage failed: {}",
+            api_response.error.unwrap_or_default()
+        );
+    }
+    Ok(http::Response::builder().status(status).body(bytes)?.into())
+}
+
+/// Escapes control characters of mrkdwn.
+/// See: https://api.slack.com/reference/surfaces/formatting#escaping
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest_middleware::ClientWithMiddleware as Client;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_partial_json, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use url::Url;
+
+    use super::{Channel, Message};
+    use crate::twitter::Tweet;
+
+    fn tweet() -> Tweet {
+        serde_json::from_value(json!({
+            "id": "1587946527955329024",
+            "created_at": "2022-11-02T23:15:29.000Z",
+            "text": "Q&A <today>",
+            "media": [
+                {
+                    "media_key": "3_1587519845523918848",
+                    "type": "photo",
+                    "url": "https://pbs.twimg.com/media/FgdmR8eXEAA7ILm.jpg"
+                }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn blocks() {
+        let message = serde_json::to_value(Message::new("TwitterDev", tweet())).unwrap();
+        assert_eq!(
+            json!([
+                {
+                    "type": "section",
+                    "text": {
+                        "type": "mrkdwn",
+                        "text": "Q&amp;A &lt;today&gt;\n<https://twitter.com/TwitterDev/status/1587946527955329024|Open on Twitter>"
+                    }
+                },
+                {
+                    "type": "context",
+                    "elements": [
+                        {
+                            "type": "mrkdwn",
+                            "text": "<https://twitter.com/TwitterDev|@TwitterDev> · 2022-11-02T23:15:29.000Z"
+                        }
+                    ]
+                },
+                {
+                    "type": "image",
+                    "image_url": "https://pbs.twimg.com/media/FgdmR8eXEAA7ILm.jpg",
+                    "alt_text": "Media of @TwitterDev's tweet"
+                }
+            ]),
+            message["blocks"]
+        );
+        assert!(message.get("channel").is_none());
+    }
+
+    #[tokio::test]
+    async fn retry_after() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/services/T000/B000/XXXX"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/services/T000/B000/XXXX"))
+            .and(body_partial_json(json!({"text": "Q&A <today>"})))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let webhook_url = format!("{}/services/T000/B000/XXXX", server.uri());
+        let response = Message::new("TwitterDev", tweet())
+            .send(&Client::default(), Channel::Webhook(&webhook_url))
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+    }
+
+    #[tokio::test]
+    async fn chat_post_message() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat.postMessage"))
+            .and(header("Authorization", "Bearer xoxb-xxx"))
+            .and(body_partial_json(json!({"channel": "C0123456789"})))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"ok": true, "ts": "1503435956.000247"})),
+            )
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat.postMessage"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"ok": false, "error": "channel_not_found"})),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = Url::parse(&format!("{}/api/", server.uri())).unwrap();
+        let channel = || Channel::Bot {
+            api: &api,
+            token: "xoxb-xxx",
+            channel: "C0123456789",
+        };
+        let response = Message::new("TwitterDev", tweet())
+            .send(&Client::default(), channel())
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        // Web API responds to errors with status OK.
+        let err = Message::new("TwitterDev", tweet())
+            .send(&Client::default(), channel())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("channel_not_found"));
+    }
+}
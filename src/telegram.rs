@@ -1,10 +1,18 @@
-use anyhow::{Context, Result};
-use reqwest::{Client, Response};
-use serde::Serialize;
+use anyhow::{anyhow, Result};
+use reqwest::{Response, StatusCode};
+use reqwest_middleware::ClientWithMiddleware as Client;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
 use crate::twitter::Tweet;
 
+/// Default Telegram Bot API base URL.
+pub(crate) const API_BASE: &str = "https://api.telegram.org/";
+
+/// Maximum length of message text.
+/// See: https://core.telegram.org/bots/api#sendmessage
+const MAX_TEXT_LENGTH: usize = 4096;
+
 /// A message sent by Telegram bot.
 #[derive(Debug, Serialize)]
 pub(crate) struct Message {
@@ -12,32 +20,431 @@ pub(crate) struct Message {
     chat_id: String,
     /// Message text body.
     text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message_thread_id: Option<i64>,
+    /// Sends the message silently, users receive a notification with no sound.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    disable_notification: bool,
+    /// Protects the message from forwarding and saving.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    protect_content: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    link_preview_options: Option<LinkPreviewOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_markup: Option<InlineKeyboardMarkup>,
+    /// Tweet which buttons link to, digest messages have none.
+    #[serde(skip)]
+    tweet: Option<Tweet>,
+}
+
+/// Bot API options of messages sent to a chat.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub(crate) struct Options {
+    /// Sends messages silently, users receive a notification with no sound.
+    #[serde(default)]
+    pub(crate) disable_notification: bool,
+    /// How link previews of messages are shown, default is decided by Telegram.
+    pub(crate) link_preview: Option<LinkPreview>,
+    /// Protects messages from forwarding and saving.
+    #[serde(default)]
+    pub(crate) protect_content: bool,
+    /// Topic of a forum supergroup that messages are sent to.
+    pub(crate) message_thread_id: Option<i64>,
+    /// Buttons attached below messages, digest messages have no buttons.
+    #[serde(default)]
+    pub(crate) buttons: Vec<Button>,
+}
+
+/// An inline keyboard button linking to Twitter, configured by its kind only, like "open",
+/// or by a table with its label, like `{ kind = "open", text = "Open on X" }`.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(from = "ButtonConfig")]
+pub(crate) struct Button {
+    pub(crate) kind: ButtonKind,
+    /// Label of the button, default depends on its kind.
+    pub(crate) text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ButtonConfig {
+    Kind(ButtonKind),
+    Labeled {
+        kind: ButtonKind,
+        text: Option<String>,
+    },
+}
+
+/// What an inline keyboard button links to.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ButtonKind {
+    /// Opens the tweet.
+    Open,
+    /// Opens the tweet author's profile.
+    Profile,
+    Like,
+    Retweet,
+    Reply,
+}
+
+impl From<ButtonConfig> for Button {
+    fn from(config: ButtonConfig) -> Self {
+        match config {
+            ButtonConfig::Kind(kind) => Self { kind, text: None },
+            ButtonConfig::Labeled { kind, text } => Self { kind, text },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LinkPreview {
+    Disabled,
+    SmallMedia,
+    LargeMedia,
+}
+
+/// See: https://core.telegram.org/bots/api#inlinekeyboardmarkup
+#[derive(Debug, Serialize)]
+struct InlineKeyboardMarkup {
+    inline_keyboard: Vec<Vec<InlineKeyboardButton>>,
+}
+
+/// See: https://core.telegram.org/bots/api#inlinekeyboardbutton
+#[derive(Debug, Serialize)]
+struct InlineKeyboardButton {
+    text: String,
+    url: String,
+}
+
+/// See: https://core.telegram.org/bots/api#linkpreviewoptions
+#[derive(Debug, Serialize, Default)]
+struct LinkPreviewOptions {
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    is_disabled: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    prefer_small_media: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    prefer_large_media: bool,
 }
 
 impl Message {
     pub(crate) fn new(channel: &str, tweet: Tweet) -> Self {
+        let mut message =
+            Self::with_text(channel, format!("{}\n\n{}", tweet.text, tweet.created_at));
+        message.tweet = Some(tweet);
+        message
+    }
+
+    fn with_text(channel: &str, text: String) -> Self {
         Self {
             chat_id: channel.into(),
-            text: format!("{}\n\n{}", tweet.text, tweet.created_at),
+            text,
+            message_thread_id: None,
+            disable_notification: false,
+            protect_content: false,
+            link_preview_options: None,
+            reply_markup: None,
+            tweet: None,
+        }
+    }
+
+    pub(crate) fn options(mut self, options: &Options) -> Self {
+        self.message_thread_id = options.message_thread_id;
+        self.disable_notification = options.disable_notification;
+        self.protect_content = options.protect_content;
+        self.link_preview_options = options.link_preview.map(|link_preview| {
+            let mut link_preview_options = LinkPreviewOptions::default();
+            match link_preview {
+                LinkPreview::Disabled => link_preview_options.is_disabled = true,
+                LinkPreview::SmallMedia => link_preview_options.prefer_small_media = true,
+                LinkPreview::LargeMedia => link_preview_options.prefer_large_media = true,
+            }
+            link_preview_options
+        });
+        self
+    }
+
+    /// Attaches an inline keyboard of buttons linking to the tweet and its author.
+    pub(crate) fn buttons(mut self, buttons: &[Button], twitter_username: &str) -> Self {
+        let Some(tweet) = &self.tweet else {
+            return self;
+        };
+        if buttons.is_empty() {
+            return self;
+        }
+        let tweet_id = &tweet.id;
+        let row = buttons
+            .iter()
+            .map(|button| {
+                let (text, url) = match button.kind {
+                    ButtonKind::Open => ("Open on Twitter".into(), tweet.url(twitter_username)),
+                    ButtonKind::Profile => (
+                        format!("@{twitter_username}"),
+                        format!("https://twitter.com/{twitter_username}"),
+                    ),
+                    ButtonKind::Like => (
+                        "Like".into(),
+                        format!("https://twitter.com/intent/like?tweet_id={tweet_id}"),
+                    ),
+                    ButtonKind::Retweet => (
+                        "Retweet".into(),
+                        format!("https://twitter.com/intent/retweet?tweet_id={tweet_id}"),
+                    ),
+                    ButtonKind::Reply => (
+                        "Reply".into(),
+                        format!("https://twitter.com/intent/tweet?in_reply_to={tweet_id}"),
+                    ),
+                };
+                InlineKeyboardButton {
+                    text: button.text.clone().unwrap_or(text),
+                    url,
+                }
+            })
+            .collect();
+        self.reply_markup = Some(InlineKeyboardMarkup {
+            inline_keyboard: vec![row],
+        });
+        self
+    }
+
+    /// Sends the message without notification if `silent`, whatever the options are.
+    pub(crate) fn silent(mut self, silent: bool) -> Self {
+        self.disable_notification |= silent;
+        self
+    }
+
+    /// Creates digest messages of tweets with links to them, each message is under the text length limit.
+    /// Messages are paired with how many of the tweets they contain, in order.
+    pub(crate) fn digest(channel: &str, tweets: &[(&str, Tweet)]) -> Vec<(Self, usize)> {
+        let mut texts = vec![];
+        let mut text = String::new();
+        let mut count = 0;
+        for (twitter_username, tweet) in tweets {
+            let entry = format!(
+                "@{twitter_username} · {}\n{}\n{}\n\n",
+                tweet.created_at,
+                tweet.text,
+                tweet.url(twitter_username)
+            );
+            // Cut off a single tweet which is too long, its link is lost but still better than failing.
+            let entry: String = entry.chars().take(MAX_TEXT_LENGTH).collect();
+            if !text.is_empty() && text.chars().count() + entry.chars().count() > MAX_TEXT_LENGTH {
+                texts.push((std::mem::take(&mut text), count));
+                count = 0;
+            }
+            text.push_str(&entry);
+            count += 1;
         }
+        if !text.is_empty() {
+            texts.push((text, count));
+        }
+        texts
+            .into_iter()
+            .map(|(text, count)| (Self::with_text(channel, text.trim_end().into()), count))
+            .collect()
     }
 
-    pub(crate) async fn send(&self, client: &Client, telegram_token: &str) -> Result<Response> {
+    pub(crate) async fn send(
+        &self,
+        client: &Client,
+        api: &Url,
+        telegram_token: &str,
+    ) -> Result<Response> {
         Ok(client
-            .post(endpoint(telegram_token)?)
+            .post(endpoint(api, telegram_token)?)
             .json(self)
             .send()
             .await?)
     }
 }
 
+/// Error response of Bot API.
+/// See: https://core.telegram.org/bots/api#making-requests
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    description: String,
+}
+
+/// Returns the description of an error response, or the response body itself.
+pub(crate) fn error_description(body: &str) -> String {
+    serde_json::from_str::<ErrorResponse>(body)
+        .map(|response| response.description)
+        .unwrap_or_else(|_| body.into())
+}
+
+/// Whether Telegram would reject a message again however many times it's sent,
+/// for example, because of a bad entity or a caption that is too long.
+///
+/// Errors about the chat itself, like a missing chat or topic, or lack of rights,
+/// are fixed in config or Telegram, so they're not permanent for the message.
+pub(crate) fn is_permanent_error(status: StatusCode, body: &str) -> bool {
+    if status != StatusCode::BAD_REQUEST {
+        return false;
+    }
+    let description = error_description(body).to_lowercase();
+    !["chat", "thread", "topic", "rights"]
+        .iter()
+        .any(|word| description.contains(word))
+}
+
 /// An endpoint for sending messages by Telegram bot.
 /// See: https://core.telegram.org/bots/api#sendmessage
-fn endpoint(token: &str) -> Result<Url> {
-    let api = Url::parse("https://api.telegram.org/")
-        .with_context(|| "Could not parse Telegram api base endpoint")?;
-    Url::options()
-        .base_url(Some(&api))
-        .parse(format!("/bot{token}/sendMessage").as_str())
-        .with_context(|| "could not parse Telegram api path")
+fn endpoint(api: &Url, token: &str) -> Result<Url> {
+    // Note: a bot token contains a colon, which would be taken as a URL scheme by `Url::join`.
+    let mut url = api.clone();
+    url.path_segments_mut()
+        .map_err(|_| anyhow!("Telegram api base URL cannot be a base"))?
+        .pop_if_empty()
+        .extend([format!("bot{token}").as_str(), "sendMessage"]);
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+    use serde_json::json;
+
+    use url::Url;
+
+    use super::{
+        endpoint, is_permanent_error, Button, ButtonKind, LinkPreview, Message, Options,
+        MAX_TEXT_LENGTH,
+    };
+    use crate::twitter::Tweet;
+
+    fn tweet(id: &str, text: &str) -> Tweet {
+        serde_json::from_value(json!({
+            "id": id,
+            "created_at": "2022-11-02T23:15:29.000Z",
+            "text": text
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn api_endpoint() {
+        let api = Url::parse("http://localhost:8081/telegram/").unwrap();
+        assert_eq!(
+            "http://localhost:8081/telegram/bot123:abc/sendMessage",
+            endpoint(&api, "123:abc").unwrap().as_str()
+        );
+    }
+
+    #[test]
+    fn permanent_error() {
+        let error = |description: &str| {
+            json!({"ok": false, "error_code": 400, "description": description}).to_string()
+        };
+        assert!(is_permanent_error(
+            StatusCode::BAD_REQUEST,
+            &error("Bad Request: can't parse entities: Unsupported start tag \"x\"")
+        ));
+        assert!(is_permanent_error(
+            StatusCode::BAD_REQUEST,
+            &error("Bad Request: message caption is too long")
+        ));
+        assert!(!is_permanent_error(
+            StatusCode::BAD_REQUEST,
+            &error("Bad Request: chat not found")
+        ));
+        assert!(!is_permanent_error(
+            StatusCode::BAD_REQUEST,
+            &error("Bad Request: message thread not found")
+        ));
+        assert!(!is_permanent_error(
+            StatusCode::TOO_MANY_REQUESTS,
+            &error("Too Many Requests: retry after 5")
+        ));
+    }
+
+    #[test]
+    fn options() {
+        let options = Options {
+            link_preview: Some(LinkPreview::LargeMedia),
+            message_thread_id: Some(42),
+            ..Default::default()
+        };
+        let message = Message::new("@some_group", tweet("1", "Hello"))
+            .options(&options)
+            .silent(true);
+        assert_eq!(
+            json!({
+                "chat_id": "@some_group",
+                "text": "Hello\n\n2022-11-02T23:15:29.000Z",
+                "message_thread_id": 42,
+                "disable_notification": true,
+                "link_preview_options": {"prefer_large_media": true}
+            }),
+            serde_json::to_value(message).unwrap()
+        );
+    }
+
+    #[test]
+    fn buttons() {
+        let options: Options = toml::from_str(
+            r#"buttons = [{ kind = "open", text = "Open on X" }, "profile", { kind = "like" }]"#,
+        )
+        .unwrap();
+        let message = Message::new("@some_channel", tweet("1", "Hello"))
+            .buttons(&options.buttons, "TwitterDev");
+        assert_eq!(
+            json!({
+                "inline_keyboard": [[
+                    {"text": "Open on X", "url": "https://twitter.com/TwitterDev/status/1"},
+                    {"text": "@TwitterDev", "url": "https://twitter.com/TwitterDev"},
+                    {"text": "Like", "url": "https://twitter.com/intent/like?tweet_id=1"}
+                ]]
+            }),
+            serde_json::to_value(message).unwrap()["reply_markup"]
+        );
+
+        let messages = Message::digest("@some_channel", &[("TwitterDev", tweet("1", "Hello"))]);
+        let (message, _) = messages.into_iter().next().unwrap();
+        let message = serde_json::to_value(message.buttons(
+            &[Button {
+                kind: ButtonKind::Open,
+                text: None,
+            }],
+            "TwitterDev",
+        ))
+        .unwrap();
+        assert!(message.get("reply_markup").is_none());
+    }
+
+    #[test]
+    fn digest() {
+        let tweets = [
+            ("TwitterDev", tweet("1", "First")),
+            ("jack", tweet("2", "Second")),
+        ];
+        let messages = Message::digest("some_bot", &tweets);
+        assert_eq!(1, messages.len());
+        assert_eq!(2, messages[0].1);
+        assert_eq!(
+            "@TwitterDev · 2022-11-02T23:15:29.000Z\nFirst\nhttps://twitter.com/TwitterDev/status/1\n\n\
+             @jack · 2022-11-02T23:15:29.000Z\nSecond\nhttps://twitter.com/jack/status/2",
+            messages[0].0.text
+        );
+
+        let long_text = "a".repeat(3000);
+        let tweets = [
+            ("TwitterDev", tweet("1", &long_text)),
+            ("TwitterDev", tweet("2", &long_text)),
+            ("TwitterDev", tweet("3", "Short")),
+        ];
+        let messages = Message::digest("some_bot", &tweets);
+        assert_eq!(
+            vec![1, 2],
+            messages.iter().map(|(_, count)| *count).collect::<Vec<_>>()
+        );
+        assert!(messages
+            .iter()
+            .all(|(message, _)| message.text.chars().count() <= MAX_TEXT_LENGTH));
+        assert!(messages[1]
+            .0
+            .text
+            .ends_with("https://twitter.com/TwitterDev/status/3"));
+    }
 }
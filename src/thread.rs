@@ -0,0 +1,95 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::database::{Database, WriteBatch};
+
+/// Default time a published status is kept in thread column family for replies to find it.
+pub(crate) const DEFAULT_MAX_AGE: Duration = Duration::days(30);
+
+/// A Mastodon status published for a tweet, kept in thread column family so that replies
+/// to the tweet can be threaded.
+///
+/// It's keyed by destination ID and tweet ID joined by a slash.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub(crate) struct Thread {
+    pub(crate) status_id: String,
+    /// RFC3339 time the status is published at, which `thread_max_age` is applied to.
+    pub(crate) published_at: String,
+}
+
+impl Thread {
+    pub(crate) fn new(status_id: String, published_at: &str) -> Self {
+        Self {
+            status_id,
+            published_at: published_at.into(),
+        }
+    }
+
+    pub(crate) fn to_vec(&self) -> Result<Vec<u8>> {
+        serde_json::to_vec(self).with_context(|| "could not serialize thread to json")
+    }
+
+    pub(crate) fn from_slice(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes).with_context(|| "Invalid thread entry")
+    }
+}
+
+/// Deletes thread entries published earlier than `max_age`, returns how many are deleted.
+pub(crate) fn prune(database: &Database, max_age: Duration, now: DateTime<Utc>) -> Result<usize> {
+    let mut batch = WriteBatch::default();
+    for entry in database.iterator_cf("thread")? {
+        let (key, value) = entry?;
+        let thread = Thread::from_slice(&value)?;
+        let published_at = DateTime::parse_from_rfc3339(&thread.published_at)
+            .with_context(|| format!("Invalid publishing time of thread {}", thread.status_id))?;
+        if published_at.to_utc() + max_age < now {
+            batch.delete_cf("thread", key);
+        }
+    }
+    let pruned = batch.len();
+    database.write(batch)?;
+    if pruned > 0 {
+        info!("Pruned {pruned} Mastodon threads.");
+    }
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration};
+
+    use super::{prune, Thread};
+    use crate::database::Database;
+
+    #[test]
+    fn prune_threads() {
+        let database = Database::memory();
+        let now = DateTime::parse_from_rfc3339("2024-01-10T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        for (tweet_id, published_at) in [
+            ("1001", "2024-01-01T00:00:00Z"),
+            ("1002", "2024-01-09T00:00:00Z"),
+        ] {
+            let thread = Thread::new(format!("{tweet_id}42"), published_at);
+            database
+                .put_cf(
+                    "thread",
+                    format!("mastodon:https://mastodon.social/{tweet_id}"),
+                    thread.to_vec().unwrap(),
+                )
+                .unwrap();
+        }
+        assert!(Thread::from_slice(b"100042").is_err());
+
+        assert_eq!(1, prune(&database, Duration::days(7), now).unwrap());
+        let keys: Vec<_> = database
+            .iterator_cf("thread")
+            .unwrap()
+            .map(|entry| String::from_utf8(entry.unwrap().0.to_vec()).unwrap())
+            .collect();
+        assert_eq!(vec!["mastodon:https://mastodon.social/1002"], keys);
+    }
+}
@@ -1,7 +1,13 @@
+mod archive;
 mod timeline;
 mod users;
 
+pub(crate) use archive::TwitterArchive;
+pub(crate) use timeline::{Data as Tweet, Media, MediaFile, PaginationToken, Timeline, UrlBuilder};
 pub(crate) use users::Users;
-pub(crate) use timeline::{Timeline, UrlBuilder, PaginationToken, Data as Tweet};
 
-const API_ENDPOINT_BASE: &str = "https://api.twitter.com/2/";
+#[cfg(test)]
+pub(crate) use archive::tests::archive_zip;
+
+/// Default Twitter API base URL.
+pub(crate) const API_ENDPOINT_BASE: &str = "https://api.twitter.com/2/";
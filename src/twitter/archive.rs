@@ -0,0 +1,258 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, SecondsFormat};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::io::{Read, Seek};
+use tracing::warn;
+use zip::{result::ZipError, ZipArchive};
+
+use super::{timeline::Data, Media, MediaFile};
+
+/// Tweets of an account read from an official Twitter archive ZIP.
+/// See: https://help.twitter.com/en/managing-your-account/how-to-download-your-twitter-archive
+#[derive(Debug)]
+pub(crate) struct TwitterArchive {
+    /// Username of the account, if the archive has `data/account.js`.
+    pub(crate) username: Option<String>,
+    /// Tweets in the shape of timeline api, in no particular order.
+    pub(crate) tweets: Vec<Data>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AccountItem {
+    account: Account,
+}
+
+#[derive(Debug, Deserialize)]
+struct Account {
+    username: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TweetItem {
+    tweet: ArchivedTweet,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArchivedTweet {
+    id_str: String,
+    /// In the form of "Wed Oct 10 20:19:24 +0000 2018".
+    created_at: String,
+    full_text: String,
+    in_reply_to_status_id_str: Option<String>,
+    extended_entities: Option<Entities>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Entities {
+    #[serde(default)]
+    media: Vec<ArchivedMedia>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArchivedMedia {
+    id_str: String,
+    /// One of "photo", "video" or "animated_gif".
+    #[serde(rename = "type")]
+    typ: String,
+    media_url_https: String,
+}
+
+impl TwitterArchive {
+    pub(crate) fn read<R: Read + Seek>(reader: R) -> Result<Self> {
+        let mut zip = ZipArchive::new(reader).with_context(|| "Invalid Twitter archive ZIP")?;
+        // Archives put tweets in `data/tweets.js`, older ones in `data/tweet.js`,
+        // and large ones split them into `data/tweets-part1.js` and so on.
+        let mut names: Vec<String> = zip
+            .file_names()
+            .filter(|name| {
+                name.strip_prefix("data/tweet")
+                    .and_then(|rest| rest.strip_suffix(".js"))
+                    .is_some_and(|rest| {
+                        matches!(rest, "" | "s")
+                            || rest
+                                .strip_prefix("s-part")
+                                .is_some_and(|n| n.chars().all(|c| c.is_ascii_digit()))
+                    })
+            })
+            .map(String::from)
+            .collect();
+        if names.is_empty() {
+            return Err(anyhow!("No data/tweets.js in Twitter archive"));
+        }
+        names.sort();
+
+        let username = match zip.by_name("data/account.js") {
+            Ok(file) => read_js::<AccountItem>(file)?
+                .into_iter()
+                .next()
+                .map(|item| item.account.username),
+            Err(_) => None,
+        };
+        let mut tweets = vec![];
+        for name in names {
+            let items = read_js::<TweetItem>(zip.by_name(&name)?)
+                .with_context(|| format!("Invalid {name}"))?;
+            for item in items {
+                tweets.push(item.tweet.into_data(&mut zip)?);
+            }
+        }
+        Ok(Self { username, tweets })
+    }
+}
+
+impl ArchivedTweet {
+    fn into_data<R: Read + Seek>(self, zip: &mut ZipArchive<R>) -> Result<Data> {
+        let created_at = DateTime::parse_from_str(&self.created_at, "%a %b %d %H:%M:%S %z %Y")
+            .with_context(|| format!("Invalid creation time of tweet {}", self.id_str))?
+            .to_utc()
+            .to_rfc3339_opts(SecondsFormat::Millis, true);
+        let media = self
+            .extended_entities
+            .map(|entities| entities.media)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|media| media.into_media(&self.id_str, zip))
+            .collect::<Result<_>>()?;
+        Ok(Data::new(
+            self.id_str,
+            created_at,
+            self.full_text,
+            media,
+            self.in_reply_to_status_id_str,
+        ))
+    }
+}
+
+impl ArchivedMedia {
+    /// Photos carry their files in `data/tweets_media/`, so that destinations uploading images
+    /// don't depend on Twitter still serving them.
+    fn into_media<R: Read + Seek>(self, tweet_id: &str, zip: &mut ZipArchive<R>) -> Result<Media> {
+        let is_photo = self.typ == "photo";
+        let file = match is_photo {
+            true => read_media_file(zip, tweet_id, &self.media_url_https)?,
+            false => None,
+        };
+        // Media keys of timeline api are media IDs prefixed by their type.
+        let prefix = match self.typ.as_str() {
+            "photo" => 3,
+            "video" => 7,
+            _ => 16,
+        };
+        Ok(Media {
+            media_key: format!("{prefix}_{}", self.id_str),
+            typ: self.typ,
+            url: is_photo.then(|| self.media_url_https.clone()),
+            preview_image_url: (!is_photo).then_some(self.media_url_https),
+            file,
+        })
+    }
+}
+
+/// Reads a media file of a tweet, which archives name by tweet ID and the file name of its
+/// Twitter URL. Archives may leave media out, then it's only linked from Twitter.
+fn read_media_file<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    tweet_id: &str,
+    url: &str,
+) -> Result<Option<MediaFile>> {
+    // Unwrap it directly since splitting yields at least one part.
+    let name = format!("{tweet_id}-{}", url.rsplit('/').next().unwrap());
+    let path = format!("data/tweets_media/{name}");
+    let mut file = match zip.by_name(&path) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => {
+            warn!("No {path} in Twitter archive, media is linked from Twitter instead.");
+            return Ok(None);
+        }
+        Err(err) => return Err(err).with_context(|| format!("Could not read {path}")),
+    };
+    let mut bytes = vec![];
+    file.read_to_end(&mut bytes)
+        .with_context(|| format!("Could not read {path}"))?;
+    Ok(Some(MediaFile::new(name, &bytes)))
+}
+
+/// Reads a JavaScript file of an archive, which assigns a JSON array to a global variable,
+/// for example, `window.YTD.tweets.part0 = [...]`.
+fn read_js<T: DeserializeOwned>(mut file: impl Read) -> Result<Vec<T>> {
+    let mut js = String::new();
+    file.read_to_string(&mut js)?;
+    let json = js
+        .split_once('=')
+        .map(|(_, json)| json)
+        .ok_or_else(|| anyhow!("No assignment in archive JavaScript"))?;
+    Ok(serde_json::from_str(json)?)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::{Cursor, Write};
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::TwitterArchive;
+    use crate::twitter::MediaFile;
+
+    /// Returns a Twitter archive ZIP of two tweets.
+    pub(crate) fn archive_zip() -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("data/account.js", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(
+            br#"window.YTD.account.part0 = [{"account": {"accountId": "2244994945", "username": "TwitterDev"}}]"#,
+        )
+        .unwrap();
+        zip.start_file("data/tweets.js", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(
+            br#"window.YTD.tweets.part0 = [
+              {"tweet": {
+                "id_str": "1050118621198921728",
+                "created_at": "Wed Oct 10 20:19:24 +0000 2018",
+                "full_text": "To make room for more expression",
+                "extended_entities": {"media": [{
+                  "id_str": "1050118600000000000",
+                  "type": "photo",
+                  "media_url_https": "https://pbs.twimg.com/media/xyz.jpg"
+                }]}
+              }},
+              {"tweet": {
+                "id_str": "1050118621198921729",
+                "created_at": "Wed Oct 10 20:20:00 +0000 2018",
+                "full_text": "@TwitterDev Indeed",
+                "in_reply_to_status_id_str": "1050118621198921728"
+              }}
+            ]"#,
+        )
+        .unwrap();
+        zip.start_file(
+            "data/tweets_media/1050118621198921728-xyz.jpg",
+            SimpleFileOptions::default(),
+        )
+        .unwrap();
+        zip.write_all(&[0xFF, 0xD8]).unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn read() {
+        let archive = TwitterArchive::read(Cursor::new(archive_zip())).unwrap();
+        assert_eq!(Some("TwitterDev"), archive.username.as_deref());
+        assert_eq!(2, archive.tweets.len());
+
+        let tweet = &archive.tweets[0];
+        assert_eq!("2018-10-10T20:19:24.000Z", tweet.created_at);
+        assert_eq!("3_1050118600000000000", tweet.media[0].media_key);
+        assert_eq!(
+            Some("https://pbs.twimg.com/media/xyz.jpg"),
+            tweet.media[0].image_url()
+        );
+        assert_eq!(
+            Some(MediaFile::new(
+                "1050118621198921728-xyz.jpg".into(),
+                &[0xFF, 0xD8]
+            )),
+            tweet.media[0].file
+        );
+        assert_eq!(Some("1050118621198921728"), archive.tweets[1].in_reply_to());
+    }
+}
@@ -1,12 +1,11 @@
 use anyhow::{Context, Result};
-use reqwest::Client;
+use base64::{engine::general_purpose::STANDARD, Engine};
 use reqwest::StatusCode;
+use reqwest_middleware::ClientWithMiddleware as Client;
 use serde::{Deserialize, Serialize};
 use tracing::{info, trace, warn};
 use url::Url;
 
-use super::API_ENDPOINT_BASE;
-
 /// Timeline continually yields all tweets in timeline which may be paginated.
 pub(crate) struct Timeline<'a> {
     client: &'a Client,
@@ -15,6 +14,8 @@ pub(crate) struct Timeline<'a> {
     pagination_token: Option<PaginationToken>,
     page: u8,
     texts: <Vec<Data> as IntoIterator>::IntoIter,
+    /// Whether a request failed, so that no more requests are sent.
+    failed: bool,
 }
 
 #[derive(Debug)]
@@ -37,6 +38,7 @@ impl<'a> Timeline<'a> {
             pagination_token,
             page: 0,
             texts: vec![].into_iter(),
+            failed: false,
         }
     }
 
@@ -44,6 +46,9 @@ impl<'a> Timeline<'a> {
         if let Some(text) = self.texts.next() {
             return Ok(Some(text));
         }
+        if self.failed {
+            return Ok(None);
+        }
 
         // Check if pagination token is present.
         let url = match self.pagination_token.take() {
@@ -82,8 +87,13 @@ impl<'a> Timeline<'a> {
 
                 // Increase page number on request success.
                 match timeline.data {
-                    Some(tweets) => {
+                    Some(mut tweets) => {
                         self.page += 1;
+                        if let Some(includes) = timeline.includes {
+                            tweets
+                                .iter_mut()
+                                .for_each(|tweet| tweet.attach_media(&includes.media));
+                        }
                         self.texts = tweets.into_iter();
                         Ok(self.texts.next())
                     }
@@ -94,12 +104,14 @@ impl<'a> Timeline<'a> {
                 }
             }
             StatusCode::TOO_MANY_REQUESTS => {
+                self.failed = true;
                 info!(
                 "twitter timeline endpoint rate limit reached, please wait for at least 15 mins before next try: {}",
                 response.status());
                 Ok(None)
             }
             x => {
+                self.failed = true;
                 warn!(
                     result = "request not successful",
                     status = %x,
@@ -131,6 +143,7 @@ impl<'a> Timeline<'a> {
 #[derive(Debug, Deserialize, Clone)]
 pub(crate) struct Tweets {
     data: Option<Vec<Data>>,
+    includes: Option<Includes>,
     meta: Option<Meta>,
 }
 
@@ -139,13 +152,151 @@ pub(crate) struct Data {
     pub(crate) id: String,
     pub(crate) created_at: String,
     pub(crate) text: String,
+    #[serde(default, skip_serializing)]
+    attachments: Option<Attachments>,
+    /// Media attached to this tweet, resolved from response `includes`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) media: Vec<Media>,
+    /// Tweets this tweet replies to, quotes or retweets.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    referenced_tweets: Vec<ReferencedTweet>,
+    /// Time this tweet is queued at in timeline column family, which `max_age` counts from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    queued_at: Option<String>,
+}
+
+impl Data {
+    /// Creates a tweet which is not fetched from timeline api, like one from a Twitter archive.
+    pub(crate) fn new(
+        id: String,
+        created_at: String,
+        text: String,
+        media: Vec<Media>,
+        in_reply_to: Option<String>,
+    ) -> Self {
+        Self {
+            id,
+            created_at,
+            text,
+            attachments: None,
+            media,
+            referenced_tweets: in_reply_to
+                .map(|id| ReferencedTweet {
+                    typ: "replied_to".into(),
+                    id,
+                })
+                .into_iter()
+                .collect(),
+            queued_at: None,
+        }
+    }
+
+    /// Returns this tweet marked as queued at `now`.
+    pub(crate) fn queued(self, now: &str) -> Self {
+        Self {
+            queued_at: Some(now.into()),
+            ..self
+        }
+    }
+
+    /// Returns the time this tweet is queued at, which tweets from timeline api don't have.
+    pub(crate) fn queued_at(&self) -> Option<&str> {
+        self.queued_at.as_deref()
+    }
+
+    /// Returns the link to this tweet on Twitter.
+    pub(crate) fn url(&self, username: &str) -> String {
+        format!("https://twitter.com/{username}/status/{}", self.id)
+    }
+
+    /// Returns ID of the tweet this tweet replies to.
+    pub(crate) fn in_reply_to(&self) -> Option<&str> {
+        self.referenced_tweets
+            .iter()
+            .find(|referenced| referenced.typ == "replied_to")
+            .map(|referenced| referenced.id.as_str())
+    }
+
+    /// Resolves `attachments.media_keys` of this tweet against expanded media objects.
+    fn attach_media(&mut self, media: &[Media]) {
+        if let Some(attachments) = self.attachments.take() {
+            self.media = attachments
+                .media_keys
+                .iter()
+                .filter_map(|key| media.iter().find(|media| &media.media_key == key))
+                .cloned()
+                .collect();
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct ReferencedTweet {
+    /// One of "replied_to", "quoted" or "retweeted".
+    #[serde(rename = "type")]
+    typ: String,
+    id: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct Attachments {
+    #[serde(default)]
+    media_keys: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct Includes {
+    #[serde(default)]
+    media: Vec<Media>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub(crate) struct Media {
+    pub(crate) media_key: String,
+    #[serde(rename = "type")]
+    pub(crate) typ: String,
+    /// Only present for photos.
+    pub(crate) url: Option<String>,
+    /// Only present for videos and animated GIFs.
+    pub(crate) preview_image_url: Option<String>,
+    /// Photo file read from a Twitter archive, which destinations uploading images use
+    /// instead of downloading the photo from Twitter.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) file: Option<MediaFile>,
+}
+
+impl Media {
+    /// Returns an image URL that represents this media.
+    pub(crate) fn image_url(&self) -> Option<&str> {
+        self.url.as_deref().or(self.preview_image_url.as_deref())
+    }
+}
+
+/// A media file carried along with a queued tweet.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub(crate) struct MediaFile {
+    pub(crate) name: String,
+    /// File content encoded in base64.
+    base64: String,
+}
+
+impl MediaFile {
+    pub(crate) fn new(name: String, bytes: &[u8]) -> Self {
+        Self {
+            name,
+            base64: STANDARD.encode(bytes),
+        }
+    }
+
+    pub(crate) fn bytes(&self) -> Result<Vec<u8>> {
+        STANDARD
+            .decode(&self.base64)
+            .with_context(|| format!("Invalid content of media file {}", self.name))
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 struct Meta {
-    oldest_id: Option<String>,
-    newest_id: Option<String>,
-    result_count: Option<u8>,
     next_token: Option<String>,
 }
 
@@ -154,10 +305,9 @@ struct Meta {
 pub(crate) struct UrlBuilder(Url);
 
 impl UrlBuilder {
-    pub(crate) fn new(user_id: &str) -> Result<Self> {
-        let base_url = Url::parse(API_ENDPOINT_BASE).unwrap();
+    pub(crate) fn new(base_url: &Url, user_id: &str) -> Result<Self> {
         Url::options()
-            .base_url(Some(&base_url))
+            .base_url(Some(base_url))
             .parse(format!("users/{user_id}/tweets").as_str())
             .map(Self)
             .with_context(|| "Failed to parse url from user_id segment")
@@ -170,6 +320,20 @@ impl UrlBuilder {
         self
     }
 
+    pub(crate) fn expansions(mut self, expansions: Vec<&str>) -> Self {
+        self.0
+            .query_pairs_mut()
+            .append_pair("expansions", &expansions.join(","));
+        self
+    }
+
+    pub(crate) fn media_fields(mut self, media_fields: Vec<&str>) -> Self {
+        self.0
+            .query_pairs_mut()
+            .append_pair("media.fields", &media_fields.join(","));
+        self
+    }
+
     pub(crate) fn max_results(mut self, max_results: u8) -> Self {
         self.0
             .query_pairs_mut()
@@ -202,14 +366,20 @@ impl UrlBuilder {
 
 #[cfg(test)]
 mod tests {
-    use reqwest::Client;
+    use reqwest_middleware::ClientWithMiddleware as Client;
     use tracing::debug;
+    use url::Url;
+
+    use super::{Data, PaginationToken, Timeline, Tweets, UrlBuilder};
+    use crate::twitter::API_ENDPOINT_BASE;
 
-    use super::{PaginationToken, Timeline, Tweets, UrlBuilder, API_ENDPOINT_BASE};
+    fn base_url() -> Url {
+        Url::parse(API_ENDPOINT_BASE).unwrap()
+    }
 
     #[test]
     fn build_url() {
-        let url = UrlBuilder::new("123").unwrap().build();
+        let url = UrlBuilder::new(&base_url(), "123").unwrap().build();
         assert_eq!(
             format!("{API_ENDPOINT_BASE}users/{}/tweets", "123"),
             url.as_str()
@@ -218,7 +388,7 @@ mod tests {
 
     #[test]
     fn url_queries() {
-        let url = UrlBuilder::new("")
+        let url = UrlBuilder::new(&base_url(), "")
             .unwrap()
             .tweet_fields(vec!["created_at"])
             .max_results(100)
@@ -288,6 +458,59 @@ mod tests {
         serde_json::from_str::<Tweets>(timeline_data).unwrap();
     }
 
+    #[test]
+    fn attach_media() {
+        let timeline_data = r#"
+        {
+          "data": [
+            {
+              "created_at": "2022-11-01T19:00:00.000Z",
+              "text": "Look at this https://t.co/tFQ4Eskq7t",
+              "id": "1587519847281397767",
+              "attachments": {
+                "media_keys": ["3_1587519845523918848"]
+              }
+            }
+          ],
+          "includes": {
+            "media": [
+              {
+                "media_key": "3_1587519845523918848",
+                "type": "photo",
+                "url": "https://pbs.twimg.com/media/FgdmR8eXEAA7ILm.jpg"
+              }
+            ]
+          }
+        }"#;
+
+        let timeline = serde_json::from_str::<Tweets>(timeline_data).unwrap();
+        let mut tweet = timeline.data.unwrap().remove(0);
+        tweet.attach_media(&timeline.includes.unwrap().media);
+        assert_eq!(
+            Some("https://pbs.twimg.com/media/FgdmR8eXEAA7ILm.jpg"),
+            tweet.media[0].image_url()
+        );
+    }
+
+    #[test]
+    fn in_reply_to() {
+        let tweet: Data = serde_json::from_str(
+            r#"{
+              "created_at": "2022-11-02T23:15:29.000Z",
+              "text": "We’ll still celebrate the soon-to-be-announced winners of our Chirp Developer Challenge - stay tuned for more details!",
+              "id": "1587946526617264128",
+              "referenced_tweets": [
+                {
+                  "type": "replied_to",
+                  "id": "1587946525245816832"
+                }
+              ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(Some("1587946525245816832"), tweet.in_reply_to());
+    }
+
     // To test this function:
     // RUST_LOG=debug cargo test tweets -- --ignored '[auth_token]'
     #[test_log::test(tokio::test)]
@@ -296,8 +519,8 @@ mod tests {
         let mut args = std::env::args().rev();
         let auth_token = args.next().unwrap();
 
-        let client = Client::new();
-        let endpoint = UrlBuilder::new("2244994945")
+        let client = Client::default();
+        let endpoint = UrlBuilder::new(&base_url(), "2244994945")
             .unwrap()
             .tweet_fields(vec!["created_at"])
             .max_results(10)
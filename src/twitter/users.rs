@@ -1,12 +1,10 @@
 use anyhow::{Context, Result};
-use reqwest::Client;
+use reqwest_middleware::ClientWithMiddleware as Client;
 use serde::Deserialize;
 use std::collections::HashMap;
 use tracing::warn;
 use url::Url;
 
-use super::API_ENDPOINT_BASE;
-
 /// Response from Twitter users lookup api.
 #[derive(Debug, Deserialize)]
 pub(crate) struct Users {
@@ -17,7 +15,6 @@ pub(crate) struct Users {
 #[derive(Debug, Deserialize)]
 struct Data {
     id: String,
-    name: String,
     username: String,
 }
 
@@ -25,30 +22,24 @@ struct Data {
 struct Error {
     value: String,
     detail: String,
-    title: String,
-    resource_type: String,
-    parameter: String,
-    resource_id: String,
-    #[serde(rename(deserialize = "type"))]
-    typ: String,
 }
 
 impl Users {
     /// Fetch users to return a username to user_id map.
     pub(crate) async fn fetch(
         client: &Client,
+        base_url: &Url,
         usernames: Vec<&str>,
         auth_token: &str,
     ) -> Result<Option<HashMap<String, String>>> {
-        let endpoint = Self::endpoint(usernames)?;
+        let endpoint = Self::endpoint(base_url, usernames)?;
         Self::send_request(client, endpoint, auth_token).await
     }
 
-    fn endpoint(usernames: Vec<&str>) -> Result<Url> {
-        let base_url = Url::parse(API_ENDPOINT_BASE).unwrap();
+    fn endpoint(base_url: &Url, usernames: Vec<&str>) -> Result<Url> {
         let usernames = usernames.join(",");
         let mut url = Url::options()
-            .base_url(Some(&base_url))
+            .base_url(Some(base_url))
             .parse("users/by")
             .with_context(|| "Failed to parse users look up endpoint")?;
         url.set_query(Some(format!("usernames={usernames}").as_str()));
@@ -79,10 +70,10 @@ impl Users {
             .json()
             .await
             .with_context(|| "Failed to deserialize json response")?;
-        if users.errors.is_some() {
+        for error in users.errors.unwrap_or_default() {
             warn!(
-                "Errors occurred when requesting users: {:#?}",
-                users.errors.unwrap()
+                "Error occurred when requesting user {}: {}",
+                error.value, error.detail
             );
         }
         if let Some(users) = users.data {
@@ -99,9 +90,10 @@ impl Users {
 
 #[cfg(test)]
 mod tests {
-    use reqwest::Client;
+    use reqwest_middleware::ClientWithMiddleware as Client;
     use serde_json::Result;
     use tracing::debug;
+    use url::Url;
 
     use super::Users;
     use crate::twitter::API_ENDPOINT_BASE;
@@ -109,7 +101,8 @@ mod tests {
     #[test]
     fn endpoint() {
         let usernames = vec!["john", "mick"];
-        let endpoint = Users::endpoint(usernames).unwrap();
+        let base_url = Url::parse(API_ENDPOINT_BASE).unwrap();
+        let endpoint = Users::endpoint(&base_url, usernames).unwrap();
         assert_eq!(
             format!("{API_ENDPOINT_BASE}users/by?usernames={}", "john,mick"),
             endpoint.as_str()
@@ -156,8 +149,9 @@ mod tests {
         let usernames = arg.split(',').collect();
         let auth_token = args.next().unwrap();
 
-        let client = Client::new();
-        let users = Users::fetch(&client, usernames, auth_token.as_str())
+        let client = Client::default();
+        let base_url = Url::parse(API_ENDPOINT_BASE).unwrap();
+        let users = Users::fetch(&client, &base_url, usernames, auth_token.as_str())
             .await
             .unwrap();
         if let Some(users) = users {
@@ -0,0 +1,154 @@
+use anyhow::{Context, Result};
+use hmac::{Hmac, Mac};
+use reqwest::{header::CONTENT_TYPE, Response};
+use reqwest_middleware::ClientWithMiddleware as Client;
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+
+use crate::twitter::{Media, Tweet};
+
+/// A normalized tweet posted as JSON to an outgoing webhook.
+#[derive(Debug, Serialize)]
+pub(crate) struct Payload {
+    id: String,
+    username: String,
+    url: String,
+    text: String,
+    created_at: String,
+    media: Vec<Media>,
+}
+
+/// An outgoing webhook that payloads are posted to.
+#[derive(Debug)]
+pub(crate) struct Webhook<'a> {
+    pub(crate) url: &'a str,
+    /// Extra headers sent with every request.
+    pub(crate) headers: &'a HashMap<String, String>,
+    /// Shared secret to sign request body with.
+    pub(crate) secret: Option<&'a str>,
+}
+
+impl Payload {
+    pub(crate) fn new(twitter_username: &str, tweet: Tweet) -> Self {
+        Self {
+            url: tweet.url(twitter_username),
+            id: tweet.id,
+            username: twitter_username.into(),
+            text: tweet.text,
+            created_at: tweet.created_at,
+            // Media files of archived tweets are left out, subscribers fetch media by URL.
+            media: tweet
+                .media
+                .into_iter()
+                .map(|media| Media {
+                    file: None,
+                    ..media
+                })
+                .collect(),
+        }
+    }
+
+    /// Posts payload to webhook, signed if the webhook has a secret.
+    ///
+    /// Note: failed requests are retried by the client's retry policy, like other messages.
+    pub(crate) async fn send(&self, client: &Client, webhook: &Webhook<'_>) -> Result<Response> {
+        let body =
+            serde_json::to_vec(self).with_context(|| "could not serialize payload to json")?;
+        let mut request = client
+            .post(webhook.url)
+            .header(CONTENT_TYPE, "application/json");
+        for (name, value) in webhook.headers {
+            request = request.header(name, value);
+        }
+        if let Some(secret) = webhook.secret {
+            request = request.header("X-Pigeon-Signature", signature(secret, &body));
+        }
+        Ok(request.body(body).send().await?)
+    }
+}
+
+/// Returns `sha256=` followed by hex encoded HMAC-SHA256 of body.
+fn signature(secret: &str, body: &[u8]) -> String {
+    // Unwrap it directly since HMAC accepts keys of any size.
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    let hex: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("sha256={hex}")
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest_middleware::ClientWithMiddleware as Client;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use wiremock::{
+        matchers::{body_partial_json, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::{signature, Payload, Webhook};
+    use crate::twitter::Tweet;
+
+    #[tokio::test]
+    async fn send() {
+        let server = MockServer::start().await;
+        let tweet: Tweet = serde_json::from_value(json!({
+            "id": "1587946527955329024",
+            "created_at": "2022-11-02T23:15:29.000Z",
+            "text": "We’re just a Tweet away"
+        }))
+        .unwrap();
+        let payload = Payload::new("TwitterDev", tweet);
+        let body = serde_json::to_vec(&payload).unwrap();
+
+        // Messages may have been delivered already, so they're not retried.
+        Mock::given(method("POST"))
+            .and(path("/hooks/pigeon"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/hooks/pigeon"))
+            .and(header("Authorization", "Bearer xxx"))
+            .and(header(
+                "X-Pigeon-Signature",
+                signature("secret", &body).as_str(),
+            ))
+            .and(body_partial_json(json!({
+                "username": "TwitterDev",
+                "url": "https://twitter.com/TwitterDev/status/1587946527955329024"
+            })))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let headers = HashMap::from([("Authorization".to_string(), "Bearer xxx".to_string())]);
+        let webhook = Webhook {
+            url: &format!("{}/hooks/pigeon", server.uri()),
+            headers: &headers,
+            secret: Some("secret"),
+        };
+        let response = payload.send(&Client::default(), &webhook).await.unwrap();
+        assert_eq!(503, response.status());
+        let response = payload.send(&Client::default(), &webhook).await.unwrap();
+        assert!(response.status().is_success());
+    }
+
+    #[test]
+    fn sign() {
+        // Test case 2 from RFC 4231.
+        assert_eq!(
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            signature("Jefe", b"what do ya want for nothing?")
+        );
+    }
+}
@@ -0,0 +1,418 @@
+//! End-to-end tests running `App::poll` and `App::push` against mock Twitter and Telegram servers.
+#![cfg(feature = "rocksdb")]
+
+use pigeon::{App, Config, PreviewFormat};
+use rocksdb::{Options, DB};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use wiremock::{
+    matchers::{method, path, query_param, query_param_is_missing},
+    Mock, MockBuilder, MockServer, ResponseTemplate,
+};
+
+const USER_ID: &str = "2244994945";
+const TELEGRAM_TOKEN: &str = "123:abc";
+
+/// A temporary RocksDB path which is destroyed on drop, along with its lock file.
+struct TempDatabase(PathBuf);
+
+impl TempDatabase {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("pigeon-e2e-{name}-{}", std::process::id()));
+        let _ = DB::destroy(&Options::default(), &path);
+        Self(path)
+    }
+
+    /// Returns keys left in timeline column family.
+    fn timeline(&self) -> Vec<String> {
+        let cfs = DB::list_cf(&Options::default(), &self.0).unwrap();
+        if !cfs.iter().any(|cf| cf == "timeline") {
+            return vec![];
+        }
+        let db = DB::open_cf_for_read_only(&Options::default(), &self.0, &cfs, false).unwrap();
+        let cf = db.cf_handle("timeline").unwrap();
+        db.iterator_cf(cf, rocksdb::IteratorMode::Start)
+            .map(|entry| String::from_utf8(entry.unwrap().0.to_vec()).unwrap())
+            .collect()
+    }
+}
+
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        let _ = DB::destroy(&Options::default(), &self.0);
+        let _ = std::fs::remove_file(self.0.with_extension("lock"));
+    }
+}
+
+fn tweet(id: &str, text: &str) -> Value {
+    json!({
+        "id": id,
+        "created_at": "2022-11-02T23:15:29.000Z",
+        "text": text
+    })
+}
+
+/// Mock Twitter API serving a timeline of two pages.
+async fn twitter() -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/2/users/by"))
+        .and(query_param("usernames", "TwitterDev"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [{"id": USER_ID, "name": "Twitter Dev", "username": "TwitterDev"}]
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("/2/users/{USER_ID}/tweets")))
+        .and(query_param_is_missing("pagination_token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [tweet("1003", "Third"), tweet("1002", "Second")],
+            "meta": {"result_count": 2, "next_token": "page2"}
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("/2/users/{USER_ID}/tweets")))
+        .and(query_param("pagination_token", "page2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [tweet("1001", "First")],
+            "meta": {"result_count": 1}
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    server
+}
+
+fn config(database: &TempDatabase, twitter: &MockServer, telegram: &MockServer) -> Config {
+    config_with(database, twitter, telegram, "")
+}
+
+/// Returns config of mock servers, with `extra` TOML appended to it.
+fn config_with(
+    database: &TempDatabase,
+    twitter: &MockServer,
+    telegram: &MockServer,
+    extra: &str,
+) -> Config {
+    toml::from_str(&config_toml(database, twitter, telegram, extra)).unwrap()
+}
+
+fn config_toml(
+    database: &TempDatabase,
+    twitter: &MockServer,
+    telegram: &MockServer,
+    extra: &str,
+) -> String {
+    format!(
+        r#"
+        rocksdb_path = "{}"
+        twitter_token = "xxx"
+        telegram_token = "{TELEGRAM_TOKEN}"
+        twitter_api = "{}/2/"
+        telegram_api = "{}"
+
+        [[poll]]
+        included = true
+        username = "TwitterDev"
+
+        [[push]]
+        included = true
+        from = "TwitterDev"
+        username = "@some_channel"
+        {extra}
+        "#,
+        database.0.display(),
+        twitter.uri(),
+        telegram.uri(),
+    )
+}
+
+/// Returns texts of messages sent to Telegram, in order.
+async fn sent_messages(telegram: &MockServer) -> Vec<String> {
+    telegram
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|request| request.url.path() == format!("/bot{TELEGRAM_TOKEN}/sendMessage"))
+        .map(|request| {
+            let body: Value = request.body_json().unwrap();
+            assert_eq!("@some_channel", body["chat_id"]);
+            body["text"].as_str().unwrap().to_string()
+        })
+        .collect()
+}
+
+fn send_message() -> MockBuilder {
+    Mock::given(method("POST")).and(path(format!("/bot{TELEGRAM_TOKEN}/sendMessage")))
+}
+
+async fn run(config: Config) {
+    let mut app = App::new(config).unwrap();
+    app.poll().await.unwrap();
+    app.push().await.unwrap();
+}
+
+async fn push(config: Config) {
+    App::new(config).unwrap().push().await.unwrap();
+}
+
+#[tokio::test]
+async fn poll_and_push() {
+    let database = TempDatabase::new("poll-and-push");
+    let twitter = twitter().await;
+    let telegram = MockServer::start().await;
+    send_message()
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({"ok": true, "result": {"message_id": 1}})),
+        )
+        .expect(3)
+        .mount(&telegram)
+        .await;
+
+    run(config(&database, &twitter, &telegram)).await;
+
+    assert_eq!(
+        vec![
+            "First\n\n2022-11-02T23:15:29.000Z",
+            "Second\n\n2022-11-02T23:15:29.000Z",
+            "Third\n\n2022-11-02T23:15:29.000Z",
+        ],
+        sent_messages(&telegram).await
+    );
+    assert!(database.timeline().is_empty());
+}
+
+#[tokio::test]
+async fn resume_after_rejection() {
+    let database = TempDatabase::new("resume-after-rejection");
+    let twitter = twitter().await;
+    let telegram = MockServer::start().await;
+    let ok = || {
+        ResponseTemplate::new(200).set_body_json(json!({"ok": true, "result": {"message_id": 1}}))
+    };
+    send_message()
+        .respond_with(ok())
+        .up_to_n_times(1)
+        .mount(&telegram)
+        .await;
+    send_message()
+        .respond_with(ResponseTemplate::new(400).set_body_json(
+            json!({"ok": false, "error_code": 400, "description": "Bad Request: chat not found"}),
+        ))
+        .up_to_n_times(1)
+        .mount(&telegram)
+        .await;
+    send_message().respond_with(ok()).mount(&telegram).await;
+
+    run(config(&database, &twitter, &telegram)).await;
+    // The first tweet is delivered, the second is rejected, and the rest stay queued.
+    assert_eq!(2, sent_messages(&telegram).await.len());
+    assert_eq!(
+        vec!["TwitterDev:1002", "TwitterDev:1003"],
+        database.timeline()
+    );
+
+    push(config(&database, &twitter, &telegram)).await;
+    let messages = sent_messages(&telegram).await;
+    assert_eq!(4, messages.len());
+    assert!(messages[1].starts_with("Second"));
+    assert!(messages[2].starts_with("Second"));
+    assert!(messages[3].starts_with("Third"));
+    assert!(database.timeline().is_empty());
+}
+
+#[tokio::test]
+async fn dead_letter() {
+    let database = TempDatabase::new("dead-letter");
+    let twitter = twitter().await;
+    let telegram = MockServer::start().await;
+    let ok = || {
+        ResponseTemplate::new(200).set_body_json(json!({"ok": true, "result": {"message_id": 1}}))
+    };
+    send_message()
+        .respond_with(ok())
+        .up_to_n_times(1)
+        .mount(&telegram)
+        .await;
+    send_message()
+        .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+            "ok": false,
+            "error_code": 400,
+            "description": "Bad Request: can't parse entities"
+        })))
+        .up_to_n_times(1)
+        .mount(&telegram)
+        .await;
+    send_message().respond_with(ok()).mount(&telegram).await;
+
+    run(config(&database, &twitter, &telegram)).await;
+    // The rejected tweet is moved to dead letter queue, and the rest are delivered.
+    let messages = sent_messages(&telegram).await;
+    assert_eq!(3, messages.len());
+    assert!(messages[2].starts_with("Third"));
+    assert!(database.timeline().is_empty());
+
+    App::new(config(&database, &twitter, &telegram))
+        .unwrap()
+        .dlq_retry(None)
+        .unwrap();
+    push(config(&database, &twitter, &telegram)).await;
+    let messages = sent_messages(&telegram).await;
+    assert_eq!(4, messages.len());
+    assert!(messages[3].starts_with("Second"));
+    assert!(database.timeline().is_empty());
+}
+
+#[tokio::test]
+async fn dry_run() {
+    let database = TempDatabase::new("dry-run");
+    let twitter = twitter().await;
+    let telegram = MockServer::start().await;
+    send_message()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&telegram)
+        .await;
+
+    // Previewing messages needs no Telegram token.
+    let toml_str = config_toml(&database, &twitter, &telegram, "")
+        .replace(&format!(r#"telegram_token = "{TELEGRAM_TOKEN}""#), "");
+    let mut app = App::new(toml::from_str(&toml_str).unwrap()).unwrap();
+    app.poll().await.unwrap();
+    app.push_dry_run(PreviewFormat::Json).await.unwrap();
+    drop(app);
+    // Nothing is sent, and timeline is left for a real push.
+    assert_eq!(
+        vec!["TwitterDev:1001", "TwitterDev:1002", "TwitterDev:1003"],
+        database.timeline()
+    );
+}
+
+#[tokio::test]
+async fn rate_limited() {
+    let database = TempDatabase::new("rate-limited");
+    let twitter = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/2/users/by"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [{"id": USER_ID, "name": "Twitter Dev", "username": "TwitterDev"}]
+        })))
+        .mount(&twitter)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("/2/users/{USER_ID}/tweets")))
+        .respond_with(ResponseTemplate::new(429))
+        .expect(1)
+        .mount(&twitter)
+        .await;
+    let telegram = MockServer::start().await;
+    send_message()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&telegram)
+        .await;
+
+    run(config(&database, &twitter, &telegram)).await;
+    assert!(database.timeline().is_empty());
+}
+
+#[tokio::test]
+async fn invalid_route() {
+    let database = TempDatabase::new("invalid-route");
+    let twitter = twitter().await;
+    let telegram = MockServer::start().await;
+    send_message()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&telegram)
+        .await;
+    // A Slack bot route without a channel.
+    let slack = r#"
+        [[push]]
+        included = true
+        from = "TwitterDev"
+        kind = "slack"
+        token = "xoxb-xxx"
+        "#;
+
+    let mut app = App::new(config_with(&database, &twitter, &telegram, slack)).unwrap();
+    app.poll().await.unwrap();
+    assert!(app.push().await.is_err());
+    drop(app);
+    // Nothing is delivered to other destinations either.
+    assert_eq!(3, database.timeline().len());
+}
+
+#[tokio::test]
+async fn digest_partially_sent() {
+    let database = TempDatabase::new("digest-partially-sent");
+    // Tweets too long to share a digest message.
+    let long_text = |text: &str| format!("{text} {}", "a".repeat(3000));
+    let twitter = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/2/users/by"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [{"id": USER_ID, "name": "Twitter Dev", "username": "TwitterDev"}]
+        })))
+        .mount(&twitter)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("/2/users/{USER_ID}/tweets")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [
+                tweet("1003", &long_text("Third")),
+                tweet("1002", &long_text("Second")),
+                tweet("1001", &long_text("First")),
+            ],
+            "meta": {"result_count": 3}
+        })))
+        .mount(&twitter)
+        .await;
+    let telegram = MockServer::start().await;
+    let ok = || {
+        ResponseTemplate::new(200).set_body_json(json!({"ok": true, "result": {"message_id": 1}}))
+    };
+    send_message()
+        .respond_with(ok())
+        .up_to_n_times(1)
+        .mount(&telegram)
+        .await;
+    send_message()
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .mount(&telegram)
+        .await;
+    send_message().respond_with(ok()).mount(&telegram).await;
+    // The route to @some_channel gathers tweets into digests.
+    let config = || {
+        config_with(
+            &database,
+            &twitter,
+            &telegram,
+            "mode = \"digest\"\nevery = \"1h\"",
+        )
+    };
+
+    run(config()).await;
+    // The first message of the digest is sent, the second fails and the third is never sent.
+    let messages = sent_messages(&telegram).await;
+    assert_eq!(2, messages.len());
+    assert!(messages[0].contains("First"));
+    assert_eq!(
+        vec!["TwitterDev:1002", "TwitterDev:1003"],
+        database.timeline()
+    );
+
+    // Only the rest of the digest is sent again.
+    push(config()).await;
+    let messages = sent_messages(&telegram).await;
+    assert_eq!(4, messages.len());
+    assert!(messages[2].contains("Second"));
+    assert!(messages[3].contains("Third"));
+    assert!(database.timeline().is_empty());
+}